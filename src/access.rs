@@ -0,0 +1,131 @@
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{debug, info};
+
+use crate::{cache_walk, locks::CacheLocks, sidecar, AppState};
+
+/// 记录缓存命中的最后访问时间，定期批量写入旁路元数据，避免每次命中都写磁盘；
+/// 不依赖文件系统的atime（挂载为noatime时不会更新）
+#[derive(Debug, Default)]
+pub struct AccessTracker {
+    /// 缓存文件路径 -> 最后一次命中，写入后清空
+    pending: Mutex<HashMap<PathBuf, PendingAccess>>,
+}
+
+/// 命中时间及当时读到的元数据版本，写入前用于确认缓存条目没有被替换
+#[derive(Debug)]
+struct PendingAccess {
+    accessed: u64,
+    cached_at: u64,
+    etag: String,
+}
+
+impl AccessTracker {
+    /// 记录一次命中，metadata为命中时读到的元数据
+    pub fn record(&self, cached_file: &std::path::Path, metadata: &sidecar::CacheMetadata) {
+        let mut pending = self.pending.lock().unwrap();
+        pending.insert(
+            cached_file.to_path_buf(),
+            PendingAccess {
+                accessed: sidecar::unix_now(),
+                cached_at: metadata.cached_at,
+                etag: metadata.etag.clone(),
+            },
+        );
+    }
+
+    /// 将积累的访问时间写入元数据：持有该缓存文件的写锁重新读取元数据，只修改last_accessed；
+    /// 没有元数据或条目在命中后已被重新下载（cached_at或etag不同）时跳过
+    pub async fn flush(&self, cache_dir: &str, locks: &CacheLocks) {
+        let pending = std::mem::take(&mut *self.pending.lock().unwrap());
+        if pending.is_empty() {
+            return;
+        }
+        let mut written = 0;
+        for (cached_file, access) in pending {
+            let _guard = locks.lock(&cached_file).await;
+            let Some(mut metadata) = sidecar::read(cache_dir, &cached_file).await else {
+                continue;
+            };
+            if metadata.cached_at != access.cached_at || metadata.etag != access.etag {
+                debug!("[Black Hole] Cache entry replaced since last access, skipping: {:?}", cached_file);
+                continue;
+            }
+            metadata.last_accessed = Some(metadata.last_accessed.unwrap_or(0).max(access.accessed));
+            if sidecar::write(cache_dir, &cached_file, &metadata).await.is_ok() {
+                written += 1;
+            }
+        }
+        debug!("[Black Hole] Saved last access time for {} cache files", written);
+    }
+}
+
+/// 按proxy.access_flush_interval_secs定期写入访问时间，为0时不记录访问时间
+pub fn spawn_flush_task(tracker: Arc<AccessTracker>, locks: Arc<CacheLocks>, cache_dir: String, interval_secs: u64) {
+    if interval_secs == 0 {
+        return;
+    }
+    info!("[Black Hole] Tracking cache last access time, saved every {} s", interval_secs);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(interval_secs));
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            tracker.flush(&cache_dir, &locks).await;
+        }
+    });
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ColdestParams {
+    #[serde(default = "default_coldest_limit")]
+    limit: usize,
+}
+
+fn default_coldest_limit() -> usize {
+    20
+}
+
+#[derive(Debug, Serialize)]
+pub struct ColdEntry {
+    pub file: String,
+    pub size: u64,
+    /// 最后访问时间（Unix秒），优先使用元数据中记录的时间，否则使用文件系统的atime
+    pub last_accessed: u64,
+}
+
+/// 处理 `GET /stats/coldest?limit=20`，列出最久未访问的缓存文件
+pub async fn handle_coldest(State(state): State<AppState>, Query(params): Query<ColdestParams>) -> Json<Vec<ColdEntry>> {
+    let cache_dir = state.config.proxy.cache_dir.clone();
+    let entries = tokio::task::spawn_blocking(move || {
+        let mut entries = cache_walk::collect(&cache_dir);
+        sidecar::apply_last_accessed(&cache_dir, &mut entries);
+        entries.sort_by_key(|entry| entry.accessed);
+        entries.truncate(params.limit);
+        entries
+    })
+    .await
+    .unwrap_or_default();
+
+    Json(
+        entries
+            .into_iter()
+            .map(|entry| ColdEntry {
+                file: entry.relative,
+                size: entry.size,
+                last_accessed: entry
+                    .accessed
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or(0),
+            })
+            .collect(),
+    )
+}
@@ -0,0 +1,84 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+
+use crate::{cache_entry_metadata, cache_file_path, is_safe_path, AppState};
+
+/// 缓存条目信息，不包含文件内容
+#[derive(Debug, Serialize)]
+pub struct CacheInfo {
+    pub package: String,
+    pub version: String,
+    pub file: String,
+    pub cached: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    /// 写入缓存的时间（Unix秒）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cached_at: Option<u64>,
+    /// 磁盘上是否以gzip压缩存储
+    pub compressed: bool,
+}
+
+/// 处理 `/cache/info/:package/:version/*file`，scoped包名（@scope/name）占两段；
+/// 是否命中与返回缓存文件时的规则一致（见cache_entry_metadata）
+pub async fn handle_cache_info(
+    Path(path): Path<String>,
+    State(state): State<AppState>,
+) -> Response {
+    let Some((package, version, file)) = split_info_path(&path) else {
+        return (StatusCode::BAD_REQUEST, "Expected /cache/info/:package/:version/*file").into_response();
+    };
+    if !is_safe_path(&package) || !is_safe_path(&version) || !is_safe_path(&file) {
+        return (StatusCode::FORBIDDEN, "Forbidden: Unsafe path").into_response();
+    }
+
+    let cached_file = cache_file_path(&state, &package, &version, &file);
+    let mut info = CacheInfo {
+        package,
+        version,
+        file,
+        cached: false,
+        size: None,
+        content_type: None,
+        etag: None,
+        cached_at: None,
+        compressed: false,
+    };
+
+    if let Some(metadata) = cache_entry_metadata(&state, &info.file, &cached_file).await {
+        info.cached = true;
+        info.size = Some(metadata.size);
+        info.content_type = Some(metadata.content_type);
+        info.etag = Some(metadata.etag);
+        info.cached_at = Some(metadata.cached_at);
+        info.compressed = metadata.compressed;
+    }
+
+    (StatusCode::OK, Json(info)).into_response()
+}
+
+/// 将 package/version/file 形式的路径拆分为三部分
+pub(crate) fn split_info_path(path: &str) -> Option<(String, String, String)> {
+    let path = path.trim_start_matches('/');
+    let mut segments = path.splitn(if path.starts_with('@') { 4 } else { 3 }, '/');
+    let package = if path.starts_with('@') {
+        format!("{}/{}", segments.next()?, segments.next()?)
+    } else {
+        segments.next()?.to_string()
+    };
+    let version = segments.next()?.to_string();
+    let file = segments.next()?.to_string();
+    if version.is_empty() || file.is_empty() {
+        return None;
+    }
+    Some((package, version, file))
+}
@@ -0,0 +1,84 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::{cache_info::split_info_path, cache_walk, AppState};
+
+#[derive(Debug, Deserialize)]
+pub struct SearchParams {
+    glob: String,
+    /// 最多返回的条数，不能超过proxy.cache_search_limit
+    #[serde(default)]
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchResult {
+    pub glob: String,
+    /// 匹配的缓存文件，格式为 package@version/file，按字典序排列
+    pub matches: Vec<String>,
+    /// 匹配数超过上限、结果被截断时为true
+    pub truncated: bool,
+}
+
+/// 处理 `GET /cache/search?glob=react*/umd/*`，按 package@version/file 格式匹配缓存文件
+pub async fn handle_cache_search(
+    State(state): State<AppState>,
+    Query(params): Query<SearchParams>,
+) -> Response {
+    let Some(pattern) = glob_to_regex(&params.glob) else {
+        return (StatusCode::BAD_REQUEST, "Invalid glob pattern").into_response();
+    };
+    let max = state.config.proxy.cache_search_limit;
+    let limit = params.limit.map_or(max, |limit| limit.min(max));
+
+    let cache_dir = state.config.proxy.cache_dir.clone();
+    let shard_chars = state.config.proxy.cache_shard_chars;
+    let mut matches = tokio::task::spawn_blocking(move || {
+        cache_walk::collect(&cache_dir)
+            .into_iter()
+            .filter_map(|entry| {
+                let (package, version, file) = split_info_path(cache_walk::unsharded(&entry.relative, shard_chars)?)?;
+                let display = format!("{}@{}/{}", package, version, file);
+                pattern.is_match(&display).then_some(display)
+            })
+            .collect::<Vec<_>>()
+    })
+    .await
+    .unwrap_or_default();
+
+    matches.sort();
+    let truncated = matches.len() > limit;
+    matches.truncate(limit);
+
+    Json(SearchResult {
+        glob: params.glob,
+        matches,
+        truncated,
+    })
+    .into_response()
+}
+
+/// 将glob转换为正则：`*` 匹配单段内的任意字符，`**` 跨段匹配，`?` 匹配单段内的一个字符
+pub(crate) fn glob_to_regex(glob: &str) -> Option<Regex> {
+    let mut pattern = String::from("^");
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                pattern.push_str(".*");
+            }
+            '*' => pattern.push_str("[^/]*"),
+            '?' => pattern.push_str("[^/]"),
+            other => pattern.push_str(&regex::escape(&other.to_string())),
+        }
+    }
+    pattern.push('$');
+    Regex::new(&pattern).ok()
+}
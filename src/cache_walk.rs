@@ -0,0 +1,125 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// 缓存目录中的一个文件
+#[derive(Debug, Clone)]
+pub struct CacheEntry {
+    pub path: PathBuf,
+    /// 相对缓存目录的路径，使用 `/` 分隔
+    pub relative: String,
+    pub size: u64,
+    pub modified: SystemTime,
+    /// 最后访问时间，文件系统不支持atime时等于修改时间
+    pub accessed: SystemTime,
+    /// (设备号, inode)，用于识别指向同一blob的硬链接；非Unix平台为None
+    pub inode: Option<(u64, u64)>,
+}
+
+/// 写入中的临时文件后缀，写完后重命名为最终文件名，遍历时跳过
+pub const TEMP_SUFFIX: &str = ".blackhole-tmp";
+
+/// 遍历缓存目录中的所有缓存文件，跳过根部以 `.` 开头的内部条目（元数据、blob、状态文件）
+pub fn collect(cache_dir: &str) -> Vec<CacheEntry> {
+    let root = Path::new(cache_dir);
+    let mut entries = Vec::new();
+    let Ok(top) = fs::read_dir(root) else {
+        return entries;
+    };
+    for entry in top.flatten() {
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        walk(root, &entry.path(), &mut entries);
+    }
+    entries
+}
+
+fn walk(root: &Path, path: &Path, entries: &mut Vec<CacheEntry>) {
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return;
+    };
+    if metadata.is_dir() {
+        if let Ok(children) = fs::read_dir(path) {
+            for child in children.flatten() {
+                walk(root, &child.path(), entries);
+            }
+        }
+        return;
+    }
+    if !metadata.is_file() || path.to_string_lossy().ends_with(TEMP_SUFFIX) {
+        return;
+    }
+
+    let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+    let relative = path
+        .strip_prefix(root)
+        .unwrap_or(path)
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+    entries.push(CacheEntry {
+        path: path.to_path_buf(),
+        relative,
+        size: metadata.len(),
+        modified,
+        accessed: metadata.accessed().unwrap_or(modified),
+        inode: inode(&metadata),
+    });
+}
+
+#[cfg(unix)]
+fn inode(metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn inode(_metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// 文件的硬链接数，无法获取（或非Unix平台）时返回1
+pub fn link_count(path: &Path) -> u64 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        fs::symlink_metadata(path).map(|metadata| metadata.nlink()).unwrap_or(1)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        1
+    }
+}
+
+/// 缓存文件实际占用的总字节数：指向同一inode的硬链接（去重后的相同内容）只计算一次
+pub fn disk_usage(entries: &[CacheEntry]) -> u64 {
+    let mut seen = std::collections::HashSet::new();
+    entries
+        .iter()
+        .filter(|entry| entry.inode.is_none_or(|inode| seen.insert(inode)))
+        .map(|entry| entry.size)
+        .sum()
+}
+
+/// 去掉分片目录（开启proxy.cache_shard_chars时的第一段），得到 `package/version/file` 形式的相对路径
+pub fn unsharded(relative: &str, shard_chars: usize) -> Option<&str> {
+    if shard_chars == 0 {
+        return Some(relative);
+    }
+    let (shard, rest) = relative.split_once('/')?;
+    (shard.len() == shard_chars).then_some(rest)
+}
+
+/// 删除 `start` 到 `root` 之间变为空的目录（不删除root本身）
+pub fn prune_empty_parents(root: &Path, start: &Path) {
+    let mut current = start.parent();
+    while let Some(dir) = current {
+        if dir == root || !dir.starts_with(root) || fs::remove_dir(dir).is_err() {
+            break;
+        }
+        current = dir.parent();
+    }
+}
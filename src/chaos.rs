@@ -0,0 +1,31 @@
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+use tracing::debug;
+
+use crate::ChaosConfig;
+
+/// 按chaos配置注入延迟，并按error_rate随机返回500；未启用时立即返回None
+pub async fn inject(config: &ChaosConfig) -> Option<Response> {
+    if !config.enabled {
+        return None;
+    }
+    if config.delay_ms > 0 {
+        debug!("[Black Hole] Chaos: delaying response by {} ms", config.delay_ms);
+        tokio::time::sleep(Duration::from_millis(config.delay_ms)).await;
+    }
+    if config.error_rate > 0.0 && random_unit() < config.error_rate {
+        debug!("[Black Hole] Chaos: injecting 500 error");
+        return Some((StatusCode::INTERNAL_SERVER_ERROR, "Chaos: injected error").into_response());
+    }
+    None
+}
+
+/// [0, 1) 区间的随机数；RandomState每次创建时使用不同的种子，测试用途足够
+fn random_unit() -> f64 {
+    let value = std::collections::hash_map::RandomState::new().build_hasher().finish();
+    (value >> 11) as f64 / (1u64 << 53) as f64
+}
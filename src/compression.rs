@@ -0,0 +1,205 @@
+use axum::http::{header, HeaderMap, HeaderValue};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use std::io::{Read, Write};
+use tracing::warn;
+
+use crate::CompressionConfig;
+
+/// 响应体支持的压缩编码
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Brotli,
+    Gzip,
+}
+
+impl Encoding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+        }
+    }
+}
+
+/// 解析后的Accept-Encoding请求头，按RFC 9110处理q值、`*` 和 `identity`
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct AcceptEncoding {
+    /// (小写编码名, q值)，按出现顺序保存
+    entries: Vec<(String, f32)>,
+}
+
+impl AcceptEncoding {
+    pub fn parse(value: &str) -> Self {
+        let entries = value
+            .split(',')
+            .filter_map(|item| {
+                let mut parts = item.split(';');
+                let coding = parts.next()?.trim().to_ascii_lowercase();
+                if coding.is_empty() {
+                    return None;
+                }
+                let mut quality = 1.0;
+                for param in parts {
+                    if let Some((name, value)) = param.split_once('=')
+                        && name.trim().eq_ignore_ascii_case("q")
+                    {
+                        // 无法解析的q值按拒绝处理，避免返回客户端不支持的编码
+                        quality = value
+                            .trim()
+                            .parse::<f32>()
+                            .ok()
+                            .filter(|q| (0.0..=1.0).contains(q))
+                            .unwrap_or(0.0);
+                    }
+                }
+                Some((coding, quality))
+            })
+            .collect();
+        Self { entries }
+    }
+
+    /// 合并请求中所有的Accept-Encoding头
+    pub fn from_headers(request_headers: &HeaderMap) -> Self {
+        let value = request_headers
+            .get_all(header::ACCEPT_ENCODING)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .collect::<Vec<_>>()
+            .join(",");
+        Self::parse(&value)
+    }
+
+    /// 编码的q值：优先使用显式列出的项，其次是 `*`；都未列出时identity为1，其他编码为0
+    pub fn quality(&self, coding: &str) -> f32 {
+        self.listed_quality(coding)
+            .unwrap_or(if coding == "identity" { 1.0 } else { 0.0 })
+    }
+
+    /// 显式列出或由 `*` 覆盖的编码的q值
+    fn listed_quality(&self, coding: &str) -> Option<f32> {
+        let find = |name: &str| {
+            self.entries
+                .iter()
+                .find(|(entry, _)| entry == name)
+                .map(|(_, quality)| *quality)
+        };
+        find(coding).or_else(|| find("*"))
+    }
+
+    pub fn accepts(&self, encoding: Encoding) -> bool {
+        self.quality(encoding.as_str()) > 0.0
+    }
+
+    /// 选择q值最高的压缩编码，q值相同时优先brotli；客户端显式给identity更高的q值时返回None
+    pub fn preferred(&self) -> Option<Encoding> {
+        let mut best: Option<(Encoding, f32)> = None;
+        for encoding in [Encoding::Brotli, Encoding::Gzip] {
+            let quality = self.quality(encoding.as_str());
+            if quality > 0.0 && best.is_none_or(|(_, q)| quality > q) {
+                best = Some((encoding, quality));
+            }
+        }
+        let (encoding, quality) = best?;
+        // 未列出的identity只表示可以接受，不参与偏好比较
+        let identity = self.listed_quality("identity").unwrap_or(0.0);
+        (quality >= identity).then_some(encoding)
+    }
+}
+
+/// 根据请求的Accept-Encoding选择压缩编码，优先使用brotli
+pub fn choose_encoding(request_headers: &HeaderMap) -> Option<Encoding> {
+    AcceptEncoding::from_headers(request_headers).preferred()
+}
+
+/// 客户端是否接受指定编码
+pub fn accepts(request_headers: &HeaderMap, encoding: Encoding) -> bool {
+    AcceptEncoding::from_headers(request_headers).accepts(encoding)
+}
+
+/// 响应内容随Accept-Encoding变化时设置 `Vary: Accept-Encoding`，否则中间缓存可能把压缩版本返回给不支持的客户端；
+/// 已有Vary时合并而不是覆盖，已包含时不重复添加
+pub fn set_vary_accept_encoding(response_headers: &mut HeaderMap) {
+    let mut values: Vec<String> = response_headers
+        .get_all(header::VARY)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+        .collect();
+    if values
+        .iter()
+        .any(|v| v == "*" || v.eq_ignore_ascii_case(header::ACCEPT_ENCODING.as_str()))
+    {
+        return;
+    }
+    values.push("Accept-Encoding".to_string());
+    if let Ok(value) = HeaderValue::from_str(&values.join(", ")) {
+        response_headers.insert(header::VARY, value);
+    }
+}
+
+pub fn gunzip(body: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut output = Vec::new();
+    GzDecoder::new(body).read_to_end(&mut output)?;
+    Ok(output)
+}
+
+pub fn compress(body: &[u8], encoding: Encoding) -> std::io::Result<Vec<u8>> {
+    match encoding {
+        Encoding::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(body)?;
+            encoder.finish()
+        }
+        Encoding::Brotli => {
+            let mut output = Vec::new();
+            {
+                let mut encoder = brotli::CompressorWriter::new(&mut output, 4096, 5, 22);
+                encoder.write_all(body)?;
+            }
+            Ok(output)
+        }
+    }
+}
+
+/// 按配置对响应体进行压缩，压缩成功时设置Content-Encoding响应头
+pub fn encode_body(
+    config: &CompressionConfig,
+    request_headers: &HeaderMap,
+    response_headers: &mut HeaderMap,
+    body: Vec<u8>,
+) -> Vec<u8> {
+    if !config.enabled || body.len() < config.min_bytes {
+        return body;
+    }
+
+    let content_type = response_headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("");
+    if !config.is_type_allowed(content_type) {
+        return body;
+    }
+
+    // 从这里开始响应是否压缩取决于Accept-Encoding，未压缩的响应同样需要Vary
+    set_vary_accept_encoding(response_headers);
+
+    let Some(encoding) = choose_encoding(request_headers) else {
+        return body;
+    };
+
+    match compress(&body, encoding) {
+        Ok(compressed) => {
+            response_headers.insert(
+                header::CONTENT_ENCODING,
+                HeaderValue::from_static(encoding.as_str()),
+            );
+            compressed
+        }
+        Err(e) => {
+            warn!("[Black Hole] Failed to compress response: {}", e);
+            body
+        }
+    }
+}
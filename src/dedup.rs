@@ -0,0 +1,97 @@
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use tokio::fs as async_fs;
+use tracing::{debug, warn};
+
+/// 内容寻址存储目录，位于缓存目录根部
+pub const BLOB_DIR: &str = ".blobs";
+
+/// 计算内容的SHA-256十六进制摘要
+pub fn content_hash(content: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(content))
+}
+
+/// blob按摘要前两位分目录存放，避免单个目录文件过多
+fn blob_path(cache_dir: &str, hash: &str) -> PathBuf {
+    Path::new(cache_dir).join(BLOB_DIR).join(&hash[..2]).join(hash)
+}
+
+/// 缓存文件对应的blob：按文件内容计算摘要，blob存在且与缓存文件是同一个文件（硬链接）时返回
+pub fn linked_blob(cache_dir: &str, cached_file: &Path) -> Option<PathBuf> {
+    let content = std::fs::read(cached_file).ok()?;
+    let blob = blob_path(cache_dir, &content_hash(&content));
+    same_file(&blob, cached_file).then_some(blob)
+}
+
+#[cfg(unix)]
+fn same_file(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (std::fs::metadata(a), std::fs::metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn same_file(_a: &Path, _b: &Path) -> bool {
+    false
+}
+
+/// 删除不再被任何缓存文件链接的blob（硬链接数为1），返回删除的数量和释放的字节数
+pub fn remove_unlinked_blobs(cache_dir: &str) -> (usize, u64) {
+    let blob_root = Path::new(cache_dir).join(BLOB_DIR);
+    let Ok(shards) = std::fs::read_dir(&blob_root) else {
+        return (0, 0);
+    };
+    let mut removed = 0;
+    let mut bytes = 0;
+    for shard in shards.flatten() {
+        let Ok(blobs) = std::fs::read_dir(shard.path()) else {
+            continue;
+        };
+        for blob in blobs.flatten() {
+            let path = blob.path();
+            if path.to_string_lossy().ends_with(crate::cache_walk::TEMP_SUFFIX) || crate::cache_walk::link_count(&path) != 1 {
+                continue;
+            }
+            let size = blob.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+            if std::fs::remove_file(&path).is_ok() {
+                debug!("[Black Hole] Removed unlinked blob: {:?}", path);
+                removed += 1;
+                bytes += size;
+            }
+        }
+        let _ = std::fs::remove_dir(shard.path());
+    }
+    (removed, bytes)
+}
+
+/// 将内容写入内容寻址存储，并把缓存路径硬链接到blob；
+/// 不支持硬链接时退回为直接复制内容
+pub async fn store(cache_dir: &str, cached_file: &Path, content: &[u8]) -> std::io::Result<()> {
+    let hash = content_hash(content);
+    let blob = blob_path(cache_dir, &hash);
+
+    if async_fs::metadata(&blob).await.is_err() {
+        if let Some(parent) = blob.parent() {
+            async_fs::create_dir_all(parent).await?;
+        }
+        crate::write_atomic(&blob, content).await?;
+    }
+
+    // 硬链接要求目标不存在
+    if async_fs::symlink_metadata(cached_file).await.is_ok() {
+        async_fs::remove_file(cached_file).await?;
+    }
+
+    match async_fs::hard_link(&blob, cached_file).await {
+        Ok(()) => {
+            debug!("[Black Hole] Linked {:?} to blob {}", cached_file, hash);
+            Ok(())
+        }
+        Err(e) => {
+            warn!("[Black Hole] Hard link unsupported ({}), copying content instead", e);
+            crate::write_atomic(cached_file, content).await
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tokio::fs as async_fs;
+use tokio::io::AsyncWriteExt;
+use tracing::warn;
+
+use crate::AppState;
+
+/// 探测文件名，以 `.` 开头，缓存遍历时跳过
+const PROBE_FILE: &str = ".healthz-disk";
+
+/// 缓存目录可写性检查的结果，在server.disk_check_interval_secs内复用，避免探针频繁写盘
+#[derive(Debug, Default)]
+pub struct DiskHealth {
+    last: tokio::sync::Mutex<Option<(Instant, Result<(), String>)>>,
+}
+
+impl DiskHealth {
+    /// 检查期间持有锁，并发的探针等待同一次检查的结果
+    pub async fn check(&self, cache_dir: &str, interval: Duration) -> Result<(), String> {
+        let mut last = self.last.lock().await;
+        if let Some((checked_at, result)) = last.as_ref()
+            && checked_at.elapsed() < interval
+        {
+            return result.clone();
+        }
+        let result = probe(Path::new(cache_dir)).await;
+        if let Err(e) = &result {
+            warn!("[Black Hole] Cache directory is not writable: {}", e);
+        }
+        *last = Some((Instant::now(), result.clone()));
+        result
+    }
+}
+
+/// 写入、同步并删除一个小文件；只读文件系统或磁盘已满时返回错误
+async fn probe(cache_dir: &Path) -> Result<(), String> {
+    let path = cache_dir.join(PROBE_FILE);
+    let write = async {
+        let mut file = async_fs::File::create(&path).await?;
+        file.write_all(b"ok").await?;
+        file.sync_all().await
+    };
+    let result = write.await.map_err(|e| format!("write failed: {}", e));
+    let removed = async_fs::remove_file(&path).await.map_err(|e| format!("remove failed: {}", e));
+    result.and(removed)
+}
+
+/// 处理 `GET /healthz/disk`
+pub async fn handle_healthz_disk(State(state): State<AppState>) -> Response {
+    let interval = Duration::from_secs(state.config.server.disk_check_interval_secs);
+    match state.disk_health.check(&state.config.proxy.cache_dir, interval).await {
+        Ok(()) => (StatusCode::OK, "ok").into_response(),
+        Err(e) => (StatusCode::SERVICE_UNAVAILABLE, format!("cache directory is not writable: {}", e)).into_response(),
+    }
+}
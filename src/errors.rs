@@ -0,0 +1,135 @@
+use axum::{
+    http::{header, HeaderMap, StatusCode},
+    response::{Html, IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+
+/// 错误响应体的格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorFormat {
+    Text,
+    Json,
+}
+
+#[derive(Debug, Serialize)]
+struct ErrorBody<'a> {
+    error: &'a str,
+    status: u16,
+}
+
+/// 根据Accept头判断客户端是否更偏好JSON，默认返回纯文本
+pub fn preferred_format(request_headers: &HeaderMap) -> ErrorFormat {
+    let accept = request_headers
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("");
+
+    let mut json_q: f32 = 0.0;
+    let mut text_q: f32 = 0.0;
+    for range in accept.split(',') {
+        let mut parts = range.split(';');
+        let media_type = parts.next().unwrap_or("").trim().to_ascii_lowercase();
+        let q = parts
+            .filter_map(|param| param.trim().strip_prefix("q="))
+            .find_map(|q| q.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+        match media_type.as_str() {
+            "application/json" => json_q = json_q.max(q),
+            "text/plain" | "text/html" | "text/*" => text_q = text_q.max(q),
+            _ => {}
+        }
+    }
+
+    if json_q > 0.0 && json_q >= text_q {
+        ErrorFormat::Json
+    } else {
+        ErrorFormat::Text
+    }
+}
+
+/// 浏览器直接访问时Accept中text/html的权重最高，此时返回HTML页面更友好
+pub fn prefers_html(request_headers: &HeaderMap) -> bool {
+    let accept = request_headers
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("");
+
+    let mut html_q: f32 = 0.0;
+    let mut other_q: f32 = 0.0;
+    for range in accept.split(',') {
+        let mut parts = range.split(';');
+        let media_type = parts.next().unwrap_or("").trim().to_ascii_lowercase();
+        let q = parts
+            .filter_map(|param| param.trim().strip_prefix("q="))
+            .find_map(|q| q.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+        match media_type.as_str() {
+            "text/html" | "application/xhtml+xml" => html_q = html_q.max(q),
+            "application/json" | "text/plain" => other_q = other_q.max(q),
+            _ => {}
+        }
+    }
+    html_q > 0.0 && html_q >= other_q
+}
+
+/// 404响应：浏览器得到一个简单的HTML页面，其他客户端仍按error_response返回文本或JSON
+pub fn not_found_response(request_headers: &HeaderMap, message: &str) -> Response {
+    if !prefers_html(request_headers) {
+        return error_response(request_headers, StatusCode::NOT_FOUND, message);
+    }
+    let page = format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>404 Not Found</title>
+<style>
+body {{ font-family: -apple-system, BlinkMacSystemFont, "Segoe UI", sans-serif; background: #f6f7f9; color: #333; display: flex; align-items: center; justify-content: center; height: 100vh; margin: 0; }}
+main {{ text-align: center; }}
+h1 {{ font-size: 64px; margin: 0; color: #999; }}
+p {{ font-size: 16px; word-break: break-all; }}
+</style>
+</head>
+<body>
+<main>
+<h1>404</h1>
+<p>{}</p>
+</main>
+</body>
+</html>
+"#,
+        escape_html(message)
+    );
+    (StatusCode::NOT_FOUND, Html(page)).into_response()
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// 构建错误响应，客户端偏好JSON时返回 `{"error": "...", "status": 404}`
+pub fn error_response(request_headers: &HeaderMap, status: StatusCode, message: &str) -> Response {
+    match preferred_format(request_headers) {
+        ErrorFormat::Json => (
+            status,
+            Json(ErrorBody {
+                error: message,
+                status: status.as_u16(),
+            }),
+        )
+            .into_response(),
+        ErrorFormat::Text => (status, message.to_string()).into_response(),
+    }
+}
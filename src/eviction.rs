@@ -0,0 +1,232 @@
+use axum::{extract::State, Json};
+use serde::Serialize;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, info, warn};
+
+use crate::{cache_walk, dedup, locks::CacheLocks, sidecar, AppState, ProxyConfig};
+
+/// 一次淘汰的结果
+#[derive(Debug, Default, Serialize)]
+pub struct GcReport {
+    pub files_removed: usize,
+    /// 实际释放的字节数：启用去重时删除仍有其他链接的缓存文件不释放空间
+    pub bytes_reclaimed: u64,
+    /// 删除的不再被链接的去重blob数
+    pub blobs_removed: usize,
+    /// 因超过cache_ttl_secs被删除的文件数
+    pub expired: usize,
+    /// 因超过max_cache_bytes被删除的文件数
+    pub over_size_limit: usize,
+    /// 因超过max_cache_files被删除的文件数（同时超过两个限制时计入over_size_limit）
+    pub over_file_limit: usize,
+}
+
+/// 执行淘汰：先删除超过TTL的文件，再按最近访问时间删除最久未用的文件直到总大小和文件数都低于上限；
+/// 每个文件在对应路径的写锁内删除，避免与正在写入同一文件的请求交错
+pub fn run(config: &ProxyConfig, locks: &CacheLocks) -> GcReport {
+    let mut report = GcReport::default();
+    // 先清理之前遗留的无链接blob，它们不属于任何缓存文件，也不计入下面的总大小
+    let (blobs_removed, blob_bytes) = dedup::remove_unlinked_blobs(&config.cache_dir);
+    report.blobs_removed += blobs_removed;
+    report.bytes_reclaimed += blob_bytes;
+    let mut entries = cache_walk::collect(&config.cache_dir);
+
+    if config.cache_ttl_secs > 0 {
+        remove_older_than(config, locks, &mut entries, Duration::from_secs(config.cache_ttl_secs), &mut report);
+    }
+
+    if config.max_cache_bytes > 0 || config.max_cache_files > 0 {
+        remove_least_recently_used(config, locks, &mut entries, &mut report);
+    }
+
+    report
+}
+
+/// 总大小超过max_cache_bytes或文件数超过max_cache_files时，按最近访问时间删除最久未用的文件，
+/// 直到两个限制都满足
+fn remove_least_recently_used(
+    config: &ProxyConfig,
+    locks: &CacheLocks,
+    entries: &mut [cache_walk::CacheEntry],
+    report: &mut GcReport,
+) {
+    let root = Path::new(&config.cache_dir);
+    let mut total_bytes = cache_walk::disk_usage(entries);
+    let mut total_files = entries.len();
+    let over_bytes = |bytes: u64| config.max_cache_bytes > 0 && bytes > config.max_cache_bytes;
+    let over_files = |files: usize| config.max_cache_files > 0 && files > config.max_cache_files;
+
+    if over_bytes(total_bytes) {
+        info!(
+            "[Black Hole] Cache size {} bytes exceeds max_cache_bytes {}, evicting least recently used files",
+            total_bytes, config.max_cache_bytes
+        );
+    }
+    if over_files(total_files) {
+        info!(
+            "[Black Hole] Cache file count {} exceeds max_cache_files {}, evicting least recently used files",
+            total_files, config.max_cache_files
+        );
+    }
+
+    sidecar::apply_last_accessed(&config.cache_dir, entries);
+    entries.sort_by_key(|entry| entry.accessed);
+    for entry in entries.iter() {
+        let by_bytes = over_bytes(total_bytes);
+        if !by_bytes && !over_files(total_files) {
+            break;
+        }
+        let _guard = locks.blocking_lock(&entry.path);
+        if let Some(removed) = remove_entry(&config.cache_dir, root, entry) {
+            total_bytes = total_bytes.saturating_sub(removed.bytes_freed);
+            total_files -= 1;
+            report.files_removed += 1;
+            report.bytes_reclaimed += removed.bytes_freed;
+            report.blobs_removed += removed.blob_removed as usize;
+            if by_bytes {
+                report.over_size_limit += 1;
+            } else {
+                report.over_file_limit += 1;
+            }
+        }
+    }
+}
+
+/// 写入缓存的时间：优先使用元数据中的cached_at，启用去重时新写入的文件是已有blob的硬链接，
+/// 修改时间仍是blob最初写入的时间；没有元数据时退回文件的修改时间
+fn cached_at(cache_dir: &str, entry: &cache_walk::CacheEntry) -> SystemTime {
+    match sidecar::read_sync(cache_dir, &entry.path) {
+        Some(metadata) => UNIX_EPOCH + Duration::from_secs(metadata.cached_at),
+        None => entry.modified,
+    }
+}
+
+/// 删除写入缓存早于max_age的文件，并从entries中移除已删除的条目；
+/// 写入时间在持有写锁后读取，期间被重新下载的文件不会被误删
+fn remove_older_than(
+    config: &ProxyConfig,
+    locks: &CacheLocks,
+    entries: &mut Vec<cache_walk::CacheEntry>,
+    max_age: Duration,
+    report: &mut GcReport,
+) {
+    let root = Path::new(&config.cache_dir);
+    let now = SystemTime::now();
+    entries.retain(|entry| {
+        let _guard = locks.blocking_lock(&entry.path);
+        let expired = now
+            .duration_since(cached_at(&config.cache_dir, entry))
+            .is_ok_and(|age| age > max_age);
+        if expired && let Some(removed) = remove_entry(&config.cache_dir, root, entry) {
+            report.files_removed += 1;
+            report.bytes_reclaimed += removed.bytes_freed;
+            report.blobs_removed += removed.blob_removed as usize;
+            report.expired += 1;
+            return false;
+        }
+        true
+    });
+}
+
+/// 删除一个缓存文件的结果
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct RemovedEntry {
+    /// 实际释放的磁盘空间
+    pub bytes_freed: u64,
+    /// 是否同时删除了最后一个链接对应的blob
+    pub blob_removed: bool,
+}
+
+/// 删除缓存文件及其元数据，并清理空目录，删除失败时返回None；
+/// 启用去重时缓存文件是 `.blobs` 中blob的硬链接，删除最后一个缓存链接时同时删除blob才能释放空间
+pub(crate) fn remove_entry(cache_dir: &str, root: &Path, entry: &cache_walk::CacheEntry) -> Option<RemovedEntry> {
+    let links = cache_walk::link_count(&entry.path);
+    let blob = (links > 1).then(|| dedup::linked_blob(cache_dir, &entry.path)).flatten();
+    if let Err(e) = std::fs::remove_file(&entry.path) {
+        warn!("[Black Hole] Failed to evict {}: {}", entry.relative, e);
+        return None;
+    }
+    debug!("[Black Hole] Evicted cache file: {}", entry.relative);
+    cache_walk::prune_empty_parents(root, &entry.path);
+    if let Some(meta) = sidecar::meta_path(cache_dir, &entry.path) {
+        let _ = std::fs::remove_file(&meta);
+        cache_walk::prune_empty_parents(&root.join(sidecar::META_DIR), &meta);
+    }
+
+    let mut removed = RemovedEntry {
+        bytes_freed: 0,
+        blob_removed: false,
+    };
+    match blob {
+        // 只剩blob自身的链接时删除blob
+        Some(blob) => {
+            if cache_walk::link_count(&blob) == 1 && std::fs::remove_file(&blob).is_ok() {
+                debug!("[Black Hole] Removed unlinked blob: {:?}", blob);
+                cache_walk::prune_empty_parents(&root.join(dedup::BLOB_DIR), &blob);
+                removed.bytes_freed = entry.size;
+                removed.blob_removed = true;
+            }
+        }
+        // 其他硬链接（不在blob中）仍然占用空间
+        None if links > 1 => {}
+        None => removed.bytes_freed = entry.size,
+    }
+    Some(removed)
+}
+
+/// 在阻塞线程池中执行淘汰并记录结果
+pub async fn run_in_background(config: ProxyConfig, locks: Arc<CacheLocks>) -> GcReport {
+    let report = tokio::task::spawn_blocking(move || run(&config, &locks))
+        .await
+        .unwrap_or_default();
+    info!(
+        "[Black Hole] Cache eviction removed {} files and {} blobs ({} bytes): {} expired, {} over size limit, {} over file limit",
+        report.files_removed,
+        report.blobs_removed,
+        report.bytes_reclaimed,
+        report.expired,
+        report.over_size_limit,
+        report.over_file_limit
+    );
+    report
+}
+
+/// 按cleanup_interval_secs定期删除超过max_age_secs的缓存文件，两者任一为0时不启动
+pub fn spawn_cleanup_task(config: ProxyConfig, locks: Arc<CacheLocks>) {
+    if config.max_age_secs == 0 || config.cleanup_interval_secs == 0 {
+        return;
+    }
+    info!(
+        "[Black Hole] Cache cleanup enabled: every {} s, max age {} s",
+        config.cleanup_interval_secs, config.max_age_secs
+    );
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(config.cleanup_interval_secs));
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            let config = config.clone();
+            let locks = locks.clone();
+            let max_age = config.max_age_secs;
+            let report = tokio::task::spawn_blocking(move || {
+                let mut entries = cache_walk::collect(&config.cache_dir);
+                let mut report = GcReport::default();
+                remove_older_than(&config, &locks, &mut entries, Duration::from_secs(max_age), &mut report);
+                report
+            })
+            .await
+            .unwrap_or_default();
+            info!(
+                "[Black Hole] Cache cleanup removed {} files ({} bytes) older than {} s",
+                report.files_removed, report.bytes_reclaimed, max_age
+            );
+        }
+    });
+}
+
+/// 处理 `POST /cache/gc`，立即执行一次淘汰
+pub async fn handle_cache_gc(State(state): State<AppState>) -> Json<GcReport> {
+    Json(run_in_background(state.config.proxy.clone(), state.cache_locks.clone()).await)
+}
@@ -0,0 +1,53 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::debug;
+
+/// 限制同时发往同一上游主机的请求数（proxy.max_connections_per_host），
+/// 冷启动时大量缓存未命中也不会对unpkg等上游同时打开几十个连接
+#[derive(Debug, Default)]
+pub struct HostLimiter {
+    /// 每个主机允许的并发请求数，0表示不限制
+    max_per_host: usize,
+    /// 主机（host:port） -> 信号量，首次请求该主机时创建
+    semaphores: Mutex<HashMap<String, Arc<Semaphore>>>,
+}
+
+impl HostLimiter {
+    pub fn new(max_per_host: usize) -> Self {
+        Self {
+            max_per_host,
+            semaphores: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 等待该地址所在主机的空闲名额，请求完成（包括读取响应体）前需要持有返回的许可；
+    /// 未开启限制或地址无法解析时返回None，不等待
+    pub async fn acquire(&self, url: &str) -> Option<OwnedSemaphorePermit> {
+        if self.max_per_host == 0 {
+            return None;
+        }
+        let host = host_key(url)?;
+        let semaphore = {
+            let mut semaphores = self.semaphores.lock().unwrap();
+            semaphores
+                .entry(host.clone())
+                .or_insert_with(|| Arc::new(Semaphore::new(self.max_per_host)))
+                .clone()
+        };
+        if semaphore.available_permits() == 0 {
+            debug!("[Black Hole] Waiting for a free connection to {}", host);
+        }
+        semaphore.acquire_owned().await.ok()
+    }
+}
+
+/// 地址的 host:port，端口省略时使用协议的默认端口
+fn host_key(url: &str) -> Option<String> {
+    let url = reqwest::Url::parse(url).ok()?;
+    let host = url.host_str()?;
+    match url.port_or_known_default() {
+        Some(port) => Some(format!("{}:{}", host, port)),
+        None => Some(host.to_string()),
+    }
+}
@@ -0,0 +1,38 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::{Mutex, OwnedMutexGuard};
+
+/// 按缓存路径分片的写锁，防止并发请求同时下载并写入同一个缓存文件；
+/// 不同路径可能落在同一分片上，只会多等待一次，不影响正确性
+#[derive(Debug)]
+pub struct CacheLocks {
+    shards: Vec<Arc<Mutex<()>>>,
+}
+
+impl CacheLocks {
+    pub fn new(shards: usize) -> Self {
+        Self {
+            shards: (0..shards.max(1)).map(|_| Arc::new(Mutex::new(()))).collect(),
+        }
+    }
+
+    /// 获取缓存路径对应分片的锁，持有期间其他写入者会等待；
+    /// 返回的锁不借用CacheLocks，可以移入后台任务
+    pub async fn lock(&self, cached_file: &Path) -> OwnedMutexGuard<()> {
+        self.shard(cached_file).lock_owned().await
+    }
+
+    /// 与lock相同，供淘汰、校验等在阻塞线程池中运行的任务使用，不能在异步上下文中调用
+    pub fn blocking_lock(&self, cached_file: &Path) -> OwnedMutexGuard<()> {
+        self.shard(cached_file).blocking_lock_owned()
+    }
+
+    fn shard(&self, cached_file: &Path) -> Arc<Mutex<()>> {
+        let mut hasher = DefaultHasher::new();
+        cached_file.hash(&mut hasher);
+        let index = (hasher.finish() % self.shards.len() as u64) as usize;
+        self.shards[index].clone()
+    }
+}
@@ -1,23 +1,282 @@
 use axum::{
+    error_handling::HandleErrorLayer,
     extract::{Path, State},
     http::HeaderMap,
     http::StatusCode,
     response::{IntoResponse, Response},
-    routing::get,
+    routing::{get, post},
     Router,
 };
 use regex::Regex;
 use serde::Deserialize;
-use std::{fs, path::PathBuf};
+use std::{fs, path::PathBuf, time::Duration};
 use tokio::fs as async_fs;
+use tower::{timeout::TimeoutLayer, ServiceBuilder};
 use tower_http::trace::TraceLayer;
-use tracing::{info, warn, error};
+use tracing::{debug, info, warn, error};
+
+mod access;
+mod cache_info;
+mod cache_search;
+mod cache_walk;
+mod chaos;
+mod compression;
+mod dedup;
+mod disk_health;
+mod errors;
+mod eviction;
+mod host_limit;
+mod locks;
+mod manifest;
+mod metrics;
+mod micro_cache;
+mod middleware;
+mod peer;
+mod permissions;
+mod prefetch;
+mod redact;
+mod reload;
+mod request_log;
+mod resolve;
+mod server;
+mod sidecar;
+mod upstream;
+mod verify;
+
+/// unpkg上游地址
+const UNPKG_URL: &str = "https://unpkg.com";
 
 #[derive(Debug, Deserialize, Clone)]
 struct Config {
     proxy: ProxyConfig,
     log: LogConfig,
     server: ServerConfig,
+    #[serde(default)]
+    compression: CompressionConfig,
+    #[serde(default)]
+    metrics: MetricsConfig,
+    #[serde(default)]
+    ui: UiConfig,
+    #[serde(default)]
+    cache_control: CacheControlConfig,
+    #[serde(default)]
+    charset: CharsetConfig,
+    #[serde(default)]
+    admin: AdminConfig,
+    #[serde(default)]
+    chaos: ChaosConfig,
+}
+
+impl Config {
+    /// 检查配置中的问题，返回所有问题的描述
+    fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        if !matches!(self.log.level.as_str(), "trace" | "debug" | "info" | "warn" | "error") {
+            problems.push(format!(
+                "log.level '{}' is not one of trace, debug, info, warn, error",
+                self.log.level
+            ));
+        }
+        if let Err(e) = redact::Redactor::new(&self.log.redact_patterns) {
+            problems.push(format!("log.redact_patterns: {}", e));
+        }
+        if !self.server.cache_status_header.is_empty()
+            && axum::http::HeaderName::from_bytes(self.server.cache_status_header.as_bytes()).is_err()
+        {
+            problems.push(format!(
+                "server.cache_status_header '{}' is not a valid header name",
+                self.server.cache_status_header
+            ));
+        }
+        if self.server.stream_chunk_bytes == 0 {
+            problems.push("server.stream_chunk_bytes must be greater than 0".to_string());
+        }
+        if self.server.host.trim().is_empty() {
+            problems.push("server.host must not be empty".to_string());
+        }
+        if self.metrics.latency_buckets.iter().any(|b| !b.is_finite() || *b <= 0.0) {
+            problems.push("metrics.latency_buckets must contain positive numbers".to_string());
+        }
+        if self.metrics.persist && self.metrics.persist_interval_secs == 0 {
+            problems.push("metrics.persist_interval_secs must be greater than 0".to_string());
+        }
+        for (name, mode) in [
+            ("proxy.cache_file_mode", self.proxy.cache_file_mode),
+            ("proxy.cache_dir_mode", self.proxy.cache_dir_mode),
+        ] {
+            if mode.is_some_and(|mode| mode > 0o7777) {
+                problems.push(format!("{} must be a permission mode such as 0o640", name));
+            }
+        }
+        if !(0.0..=1.0).contains(&self.chaos.error_rate) {
+            problems.push("chaos.error_rate must be between 0 and 1".to_string());
+        }
+        if (self.proxy.max_age_secs == 0) != (self.proxy.cleanup_interval_secs == 0) {
+            problems.push("proxy.max_age_secs and proxy.cleanup_interval_secs must both be set to enable cache cleanup".to_string());
+        }
+        if StatusCode::from_u16(self.proxy.proxy_disabled_status).is_err() {
+            problems.push(format!(
+                "proxy.proxy_disabled_status {} is not a valid HTTP status code",
+                self.proxy.proxy_disabled_status
+            ));
+        }
+        if let Some(ca_cert_path) = &self.proxy.ca_cert_path
+            && !std::path::Path::new(ca_cert_path).is_file()
+        {
+            problems.push(format!("proxy.ca_cert_path '{}' is not a file", ca_cert_path));
+        }
+        for (name, prefix) in [
+            ("proxy.proxy_prefix", &self.proxy.proxy_prefix),
+            ("proxy.static_prefix", &self.proxy.static_prefix),
+        ] {
+            let trimmed = prefix.trim_matches('/');
+            if trimmed.is_empty() || trimmed.contains(['*', ':', '?', '#']) {
+                problems.push(format!("{} '{}' must be a non-root path such as /static", name, prefix));
+            }
+        }
+        let mut mount_prefixes = vec![self.proxy.proxy_route_prefix(), self.proxy.static_route_prefix()];
+        for mount in &self.proxy.static_mounts {
+            let trimmed = mount.prefix.trim_matches('/');
+            if trimmed.is_empty() || trimmed.contains(['*', ':', '?', '#']) {
+                problems.push(format!(
+                    "proxy.static_mounts prefix '{}' must be a non-root path such as /assets",
+                    mount.prefix
+                ));
+            } else if mount_prefixes.contains(&mount.route_prefix()) {
+                problems.push(format!(
+                    "proxy.static_mounts prefix '{}' is already used by another route",
+                    mount.prefix
+                ));
+            }
+            mount_prefixes.push(mount.route_prefix());
+        }
+        for glob in &self.proxy.no_cache_patterns {
+            if cache_search::glob_to_regex(glob).is_none() {
+                problems.push(format!("proxy.no_cache_patterns: '{}' is not a valid glob", glob));
+            }
+        }
+        if self.proxy.x_accel_redirect && !self.proxy.x_accel_prefix.starts_with('/') {
+            problems.push(format!("proxy.x_accel_prefix '{}' must start with /", self.proxy.x_accel_prefix));
+        }
+        if self.proxy.cache_shard_chars > 8 {
+            problems.push("proxy.cache_shard_chars must be between 0 and 8".to_string());
+        }
+        for peer in &self.proxy.peer_cache_urls {
+            if !(peer.starts_with("http://") || peer.starts_with("https://")) {
+                problems.push(format!("proxy.peer_cache_urls: '{}' must be an http(s) URL", peer));
+            }
+        }
+        if let Err(e) = self.proxy.upstream_headers.to_header_map() {
+            problems.push(format!("proxy.upstream_headers: {}", e));
+        }
+
+        for (name, dir) in self.proxy.local_dirs() {
+            if !self.proxy.create_dirs_on_startup {
+                if !std::path::Path::new(dir).is_dir() {
+                    problems.push(format!(
+                        "{} '{}': does not exist and create_dirs_on_startup is disabled",
+                        name, dir
+                    ));
+                }
+            } else if let Err(problem) = check_dir_creatable(dir) {
+                problems.push(format!("{} '{}': {}", name, dir, problem));
+            }
+        }
+        for (name, dir) in self.proxy.static_dirs() {
+            if let (Ok(static_dir), Ok(cache_dir)) = (
+                std::path::absolute(dir),
+                std::path::absolute(&self.proxy.cache_dir),
+            ) && dirs_overlap(&static_dir, &cache_dir)
+            {
+                problems.push(format!(
+                    "proxy.cache_dir '{}' and {} '{}' overlap: cached files would be reachable via the local static route",
+                    self.proxy.cache_dir, name, dir
+                ));
+            }
+        }
+        for rule in &self.proxy.path_headers {
+            for (name, value) in &rule.headers {
+                if axum::http::HeaderName::from_bytes(name.as_bytes()).is_err()
+                    || axum::http::HeaderValue::from_str(value).is_err()
+                {
+                    problems.push(format!(
+                        "proxy.path_headers '{}': invalid header '{}'",
+                        rule.prefix, name
+                    ));
+                }
+            }
+        }
+        if self.ui.index_files.is_empty() {
+            problems.push("ui.index_files must not be empty".to_string());
+        }
+        for href in &self.ui.preload_links {
+            if axum::http::HeaderValue::from_str(&preload_link(href)).is_err() {
+                problems.push(format!("ui.preload_links: '{}' is not a valid Link target", href));
+            }
+        }
+        if self.admin.token.as_ref().is_some_and(|token| token.expose().trim().is_empty()) {
+            problems.push("admin.token must not be empty".to_string());
+        }
+        if let Some(mirror_dir) = &self.proxy.mirror_dir
+            && !std::path::Path::new(mirror_dir).is_dir()
+        {
+            problems.push(format!("proxy.mirror_dir '{}' is not a directory", mirror_dir));
+        }
+
+        problems
+    }
+}
+
+/// 按路径组件判断两个目录是否相同或互相包含（只做词法比较，不访问文件系统）
+fn dirs_overlap(a: &std::path::Path, b: &std::path::Path) -> bool {
+    let a = lexical_normalize(a);
+    let b = lexical_normalize(b);
+    a.starts_with(&b) || b.starts_with(&a)
+}
+
+/// 去掉 `.` 段并按 `..` 回退上一级
+fn lexical_normalize(path: &std::path::Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            std::path::Component::CurDir => {}
+            std::path::Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+/// 检查目录已存在，或者最近的已存在祖先是目录（即可以被创建）
+fn check_dir_creatable(dir: &str) -> Result<(), String> {
+    if dir.trim().is_empty() {
+        return Err("must not be empty".to_string());
+    }
+
+    let path = std::path::Path::new(dir);
+    let mut current = Some(path);
+    while let Some(candidate) = current {
+        let candidate = if candidate.as_os_str().is_empty() {
+            std::path::Path::new(".")
+        } else {
+            candidate
+        };
+        if let Ok(metadata) = fs::metadata(candidate) {
+            if metadata.is_dir() {
+                return Ok(());
+            }
+            return if candidate == path {
+                Err("exists but is not a directory".to_string())
+            } else {
+                Err(format!("parent {:?} is not a directory", candidate))
+            };
+        }
+        current = candidate.parent();
+    }
+    Err("no existing parent directory".to_string())
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -25,343 +284,4205 @@ struct ProxyConfig {
     enabled: bool,
     static_dir: String,
     cache_dir: String,
+    #[serde(default = "default_route_prefix")]
+    proxy_prefix: String,
+    #[serde(default = "default_route_prefix")]
+    static_prefix: String,
+    #[serde(default)]
+    upstream_headers: UpstreamHeaders,
+    #[serde(default)]
+    dedup: bool,
+    #[serde(default)]
+    allowed_extensions: Vec<String>,
+    #[serde(default = "default_registry_url")]
+    registry_url: String,
+    #[serde(default = "default_tag_cache_secs")]
+    tag_cache_secs: u64,
+    #[serde(default = "default_true")]
+    normalize_paths: bool,
+    #[serde(default)]
+    mirror_dir: Option<String>,
+    #[serde(default = "default_true")]
+    cache_case_insensitive: bool,
+    #[serde(default)]
+    cache_shard_chars: usize,
+    #[serde(default)]
+    compress_cache: bool,
+    #[serde(default)]
+    cache_file_mode: Option<u32>,
+    #[serde(default)]
+    cache_dir_mode: Option<u32>,
+    #[serde(default)]
+    path_headers: Vec<PathHeaderRule>,
+    #[serde(default)]
+    static_mounts: Vec<StaticMount>,
+    #[serde(default)]
+    max_versions_per_package: usize,
+    #[serde(default)]
+    cache_ttl_secs: u64,
+    #[serde(default)]
+    max_cache_bytes: u64,
+    #[serde(default)]
+    max_cache_files: usize,
+    #[serde(default = "default_cache_search_limit")]
+    cache_search_limit: usize,
+    #[serde(default)]
+    max_age_secs: u64,
+    #[serde(default)]
+    cleanup_interval_secs: u64,
+    #[serde(default)]
+    startup_probe: bool,
+    #[serde(default = "default_startup_probe_path")]
+    startup_probe_path: String,
+    #[serde(default = "default_cache_lock_shards")]
+    cache_lock_shards: usize,
+    #[serde(default = "default_true")]
+    create_dirs_on_startup: bool,
+    #[serde(default = "default_connect_timeout_secs")]
+    connect_timeout_secs: u64,
+    #[serde(default)]
+    request_timeout_secs: u64,
+    #[serde(default)]
+    ca_cert_path: Option<String>,
+    #[serde(default)]
+    danger_accept_invalid_certs: bool,
+    #[serde(default = "default_proxy_disabled_status")]
+    proxy_disabled_status: u16,
+    #[serde(default = "default_proxy_disabled_message")]
+    proxy_disabled_message: String,
+    #[serde(default)]
+    proxy_disabled_redirect: bool,
+    #[serde(default)]
+    mode: ProxyMode,
+    #[serde(default)]
+    version_aliases: std::collections::HashMap<String, String>,
+    #[serde(default)]
+    warmup_files: Vec<String>,
+    #[serde(default)]
+    offline: bool,
+    #[serde(default)]
+    allow_cache_bypass: bool,
+    #[serde(default = "default_cache_bypass_param")]
+    cache_bypass_param: String,
+    #[serde(default = "default_max_redirects")]
+    max_redirects: usize,
+    #[serde(default)]
+    redirect_passthrough: bool,
+    #[serde(default)]
+    transparent_upstream_errors: bool,
+    #[serde(default)]
+    range_passthrough: bool,
+    #[serde(default)]
+    strict_content_type: bool,
+    #[serde(default)]
+    peer_cache_urls: Vec<String>,
+    #[serde(default = "default_peer_timeout_secs")]
+    peer_timeout_secs: u64,
+    #[serde(default)]
+    no_cache_patterns: Vec<String>,
+    #[serde(default)]
+    serve_stale_on_error: bool,
+    #[serde(default)]
+    local_fallback: bool,
+    #[serde(default)]
+    micro_cache_ttl_ms: u64,
+    #[serde(default)]
+    x_accel_redirect: bool,
+    #[serde(default = "default_x_accel_prefix")]
+    x_accel_prefix: String,
+    #[serde(default = "default_micro_cache_max_entries")]
+    micro_cache_max_entries: usize,
+    #[serde(default)]
+    max_connections_per_host: usize,
+    #[serde(default = "default_access_flush_interval_secs")]
+    access_flush_interval_secs: u64,
+    #[serde(default)]
+    allow_upstream_override: bool,
+    #[serde(default)]
+    allowed_referers: Vec<String>,
+    #[serde(default = "default_true")]
+    allow_missing_referer: bool,
+    #[serde(default)]
+    referer_check_static: bool,
 }
 
-#[derive(Debug, Deserialize, Clone)]
-struct LogConfig {
-    enabled: bool,
-    level: String,
+fn default_route_prefix() -> String {
+    "/static".to_string()
 }
 
-#[derive(Debug, Deserialize, Clone)]
-struct ServerConfig {
-    port: u16,
-    host: String,
+fn default_x_accel_prefix() -> String {
+    "/_blackhole_cache".to_string()
 }
 
-#[derive(Clone)]
-struct AppState {
-    config: Config,
-    client: reqwest::Client,
-    unpkg_regex: Regex,
-    index_cache: std::sync::Arc<tokio::sync::RwLock<Option<String>>>,
+fn default_micro_cache_max_entries() -> usize {
+    1000
 }
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    // 加载配置文件
-    let config = load_config("config.toml").await?;
-    
-    // 初始化日志
-    if config.log.enabled {
-        let level = match config.log.level.as_str() {
-            "trace" => tracing::Level::TRACE,
-            "debug" => tracing::Level::DEBUG,
-            "info" => tracing::Level::INFO,
-            "warn" => tracing::Level::WARN,
-            "error" => tracing::Level::ERROR,
-            _ => tracing::Level::INFO,
-        };
-        
-        tracing_subscriber::fmt()
-            .with_max_level(level)
-            .init();
-            
-        info!("[Black Hole] Configuration loaded successfully: {:?}", config);
-    }
+fn default_access_flush_interval_secs() -> u64 {
+    60
+}
 
-    // 创建必要的目录
-    create_dirs(&config).await?;
+fn default_peer_timeout_secs() -> u64 {
+    5
+}
 
-    // 创建HTTP客户端
-    let client = reqwest::Client::new();
+fn default_max_redirects() -> usize {
+    10
+}
 
-    // 编译正则表达式，支持scoped packages（@开头的包名）
-    let unpkg_regex = Regex::new(r"^/static/(@?[^@/]+(?:/[^@/]+)?)@([^/]+)/(.+)$")?;
+fn default_cache_bypass_param() -> String {
+    "nocache".to_string()
+}
 
-    // 创建应用状态
-    let state = AppState {
-        config: config.clone(),
-        client,
-        unpkg_regex,
-        index_cache: std::sync::Arc::new(tokio::sync::RwLock::new(None)),
-    };
+fn default_proxy_disabled_status() -> u16 {
+    503
+}
 
-    // 创建路由
-    let app = Router::new()
-        .route("/static/*path", get(handle_static_request))
-        .route("/", get(handle_index))
-        .with_state(state)
-        .layer(TraceLayer::new_for_http());
+fn default_proxy_disabled_message() -> String {
+    "Proxy service not enabled".to_string()
+}
 
-    // 启动服务器
-    let addr = format!("{}:{}", config.server.host, config.server.port);
-    info!("[Black Hole] Starting server");
-    info!("[Black Hole] Server started at http://{}", addr);
-    info!("[Black Hole] Proxy feature status: {}", config.proxy.enabled);
+fn default_connect_timeout_secs() -> u64 {
+    10
+}
 
-    let listener = tokio::net::TcpListener::bind(&addr).await?;
-    axum::serve(listener, app).await?;
+fn default_cache_lock_shards() -> usize {
+    64
+}
 
-    Ok(())
+fn default_startup_probe_path() -> String {
+    "react@18.2.0/package.json".to_string()
 }
 
-async fn load_config(filename: &str) -> anyhow::Result<Config> {
-    let content = async_fs::read_to_string(filename).await?;
-    let config: Config = toml::from_str(&content)?;
-    Ok(config)
+fn default_true() -> bool {
+    true
 }
 
-async fn create_dirs(config: &Config) -> anyhow::Result<()> {
-    let dirs = vec![&config.proxy.static_dir, &config.proxy.cache_dir];
-    for dir in dirs {
-        async_fs::create_dir_all(dir).await?;
-    }
-    Ok(())
+fn default_registry_url() -> String {
+    "https://registry.npmjs.org".to_string()
 }
 
-async fn handle_index(State(state): State<AppState>) -> impl IntoResponse {
-    // 首先检查缓存
-    {
-        let cache = state.index_cache.read().await;
-        if let Some(cached_content) = cache.as_ref() {
-            info!("[Black Hole] Using cached index.html");
-            let mut headers = HeaderMap::new();
-            headers.insert(
-                axum::http::header::CONTENT_TYPE,
-                "text/html; charset=utf-8".parse().unwrap(),
-            );
-            return (StatusCode::OK, headers, cached_content.clone()).into_response();
-        }
-    }
+fn default_cache_search_limit() -> usize {
+    1000
+}
 
-    // 缓存中没有，从文件读取
-    let index_path = PathBuf::from("ui").join("index.html");
-    info!("[Black Hole] Reading index.html from file: {:?}", index_path);
+fn default_tag_cache_secs() -> u64 {
+    300
+}
 
-    match async_fs::read_to_string(&index_path).await {
-        Ok(content) => {
-            // 将内容存入缓存
-            {
-                let mut cache = state.index_cache.write().await;
-                *cache = Some(content.clone());
-            }
-            
-            info!("[Black Hole] Successfully read and cached index.html");
-            let mut headers = HeaderMap::new();
-            headers.insert(
-                axum::http::header::CONTENT_TYPE,
-                "text/html; charset=utf-8".parse().unwrap(),
-            );
-            (StatusCode::OK, headers, content).into_response()
-        }
-        Err(e) => {
-            error!("[Black Hole] Failed to read index.html: {}", e);
-            (StatusCode::NOT_FOUND, "404 - index.html file not found").into_response()
-        }
-    }
+/// 缓存未命中时的处理方式，已缓存的文件在所有模式下都从本地返回
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum ProxyMode {
+    /// 从上游下载并缓存
+    #[default]
+    Proxy,
+    /// 302重定向到上游地址，由客户端直接下载
+    Redirect,
+    /// 只返回已缓存的文件，未缓存时返回404
+    CacheOnly,
 }
 
-async fn handle_static_request(
-    Path(path): Path<String>,
-    State(state): State<AppState>,
-) -> impl IntoResponse {
-    let request_path = format!("/static/{}", path);
-    info!("[Black Hole] Received request: {}", request_path);
-
-    // 检查是否为unpkg格式
-    if let Some(captures) = state.unpkg_regex.captures(&request_path) {
-        let package_name = captures.get(1).unwrap().as_str();
-        let version = captures.get(2).unwrap().as_str();
-        let file_path = captures.get(3).unwrap().as_str();
-        
-        return handle_unpkg_request(&state, package_name, version, file_path).await;
+/// 本地静态文件按路径前缀附加的响应头
+#[derive(Debug, Deserialize, Clone)]
+struct PathHeaderRule {
+    prefix: String,
+    headers: std::collections::HashMap<String, String>,
+}
+
+/// 额外的本地静态目录，挂载在独立的路由前缀下
+#[derive(Debug, Deserialize, Clone)]
+struct StaticMount {
+    prefix: String,
+    dir: String,
+}
+
+impl StaticMount {
+    /// 规范化方式同static_route_prefix
+    fn route_prefix(&self) -> String {
+        format!("/{}", self.prefix.trim_matches('/'))
     }
+}
 
-    // 本地静态文件请求
-    handle_local_static_request(&state, &path).await
+impl PathHeaderRule {
+    /// 前缀相对于static目录，允许写成 `/static/immutable/` 或 `immutable/`
+    fn matches(&self, file_path: &str) -> bool {
+        let prefix = self.prefix.trim_start_matches('/');
+        let prefix = prefix.strip_prefix("static/").unwrap_or(prefix);
+        file_path.trim_start_matches('/').starts_with(prefix)
+    }
 }
 
-async fn handle_local_static_request(
-    state: &AppState,
-    file_path: &str,
-) -> Response {
-    // 安全路径验证
-    if !is_safe_path(file_path) {
-        warn!("[Black Hole] Detected unsafe path access: {}", file_path);
-        return (StatusCode::FORBIDDEN, "Forbidden: Unsafe path").into_response();
+impl ProxyConfig {
+    /// unpkg代理路由的前缀，规范化为以 `/` 开头、不以 `/` 结尾
+    fn proxy_route_prefix(&self) -> String {
+        format!("/{}", self.proxy_prefix.trim_matches('/'))
     }
-    
-    let local_path = PathBuf::from(&state.config.proxy.static_dir).join(file_path);
-    
-    // 验证解析后的路径是否在允许的目录内
-    if !is_path_within_allowed_dirs(&local_path, &state.config.proxy.static_dir) {
-        warn!("[Black Hole] Detected directory traversal attack: {:?}", local_path);
-        return (StatusCode::FORBIDDEN, "Forbidden: Outside allowed directory range").into_response();
+
+    /// 本地静态文件路由的前缀，规范化方式同上
+    fn static_route_prefix(&self) -> String {
+        format!("/{}", self.static_prefix.trim_matches('/'))
     }
-    
-    info!("[Black Hole] Looking for local file: {:?}", local_path);
 
-    match async_fs::read(&local_path).await {
-        Ok(content) => {
-            let mut headers = HeaderMap::new();
-            set_content_type(&mut headers, file_path);
-            
-            info!("[Black Hole] Successfully returned local file: {}", file_path);
-            (StatusCode::OK, headers, content).into_response()
+    /// 本地静态文件目录：static_dir和各static_mounts的目录
+    fn static_dirs(&self) -> Vec<(String, &String)> {
+        let mut dirs = vec![("proxy.static_dir".to_string(), &self.static_dir)];
+        for mount in &self.static_mounts {
+            dirs.push((format!("proxy.static_mounts '{}'", mount.prefix), &mount.dir));
         }
-        Err(_) => {
-            warn!("[Black Hole] File not found: {}", file_path);
-            (StatusCode::NOT_FOUND, format!("File not found: {}", file_path)).into_response()
+        dirs
+    }
+
+    /// 需要在启动时检查或创建的本地目录
+    fn local_dirs(&self) -> Vec<(String, &String)> {
+        let mut dirs = self.static_dirs();
+        dirs.push(("proxy.cache_dir".to_string(), &self.cache_dir));
+        dirs
+    }
+
+    /// 检查文件扩展名是否允许代理，未配置allowed_extensions时全部允许
+    fn is_extension_allowed(&self, file_path: &str) -> bool {
+        if self.allowed_extensions.is_empty() {
+            return true;
+        }
+        let Some(ext) = std::path::Path::new(file_path).extension().and_then(|s| s.to_str()) else {
+            return false;
+        };
+        self.allowed_extensions
+            .iter()
+            .any(|allowed| allowed.trim_start_matches('.').eq_ignore_ascii_case(ext))
+    }
+
+    /// 开启allow_cache_bypass时，查询参数中带有cache_bypass_param（且值不为0）表示跳过缓存
+    fn wants_cache_bypass(&self, query: Option<&str>) -> bool {
+        if !self.allow_cache_bypass {
+            return false;
+        }
+        query.unwrap_or("").split('&').any(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            name == self.cache_bypass_param && value != "0"
+        })
+    }
+
+    /// 按Referer的来源（scheme://host:port）检查是否在allowed_referers中，未配置时全部允许
+    fn is_referer_allowed(&self, request_headers: &HeaderMap) -> bool {
+        if self.allowed_referers.is_empty() {
+            return true;
         }
+        let Some(referer) = request_headers
+            .get(axum::http::header::REFERER)
+            .and_then(|v| v.to_str().ok())
+        else {
+            return self.allow_missing_referer;
+        };
+        let Ok(referer) = reqwest::Url::parse(referer) else {
+            return false;
+        };
+        let origin = referer.origin().ascii_serialization();
+        self.allowed_referers
+            .iter()
+            .any(|allowed| allowed.trim_end_matches('/').eq_ignore_ascii_case(&origin))
+    }
+
+    /// 查找version_aliases中 `包名@版本` 对应的固定版本
+    fn aliased_version(&self, package_name: &str, version: &str) -> Option<&str> {
+        self.version_aliases
+            .get(&format!("{}@{}", package_name, version))
+            .map(String::as_str)
     }
 }
 
-async fn handle_unpkg_request(
-    state: &AppState,
-    package_name: &str,
-    version: &str,
-    file_path: &str,
-) -> Response {
-    // 构建缓存路径，去掉版本号前的@符号以兼容Windows文件系统
-    let safe_version = version.trim_start_matches('@');
-    let cache_dir = PathBuf::from(&state.config.proxy.cache_dir)
-        .join(package_name)
-        .join(safe_version);
-    let cached_file = cache_dir.join(file_path);
+/// 令牌等敏感配置值，Debug输出时隐藏以免进入启动日志
+#[derive(Deserialize, Clone)]
+#[serde(transparent)]
+struct Secret(String);
 
-    info!("[Black Hole] Checking cache file: {:?}", cached_file.display());
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("\"***\"")
+    }
+}
 
-    // 检查缓存是否存在
-    if let Ok(content) = async_fs::read(&cached_file).await {
-        info!("[Black Hole] Using cached file: {:?}", cached_file);
-        let mut headers = HeaderMap::new();
-        set_content_type(&mut headers, file_path);
-        return (StatusCode::OK, headers, content).into_response();
+impl Secret {
+    fn expose(&self) -> &str {
+        &self.0
     }
 
-    if !state.config.proxy.enabled {
-        return (StatusCode::SERVICE_UNAVAILABLE, "Proxy service not enabled").into_response();
+    /// 以常量时间比较请求中携带的令牌，避免通过响应时间逐字节猜出令牌
+    fn matches(&self, provided: Option<&str>) -> bool {
+        use subtle::ConstantTimeEq;
+        provided.is_some_and(|provided| provided.as_bytes().ct_eq(self.0.as_bytes()).into())
     }
+}
 
-    // 从unpkg下载文件
-    let unpkg_url = format!("https://unpkg.com/{}@{}/{}", package_name, version, file_path);
-    info!("[Black Hole] Downloading from unpkg: {}", unpkg_url);
+/// 转发到上游时附加的请求头，Debug输出时隐藏值以免凭据进入日志
+#[derive(Deserialize, Clone, Default)]
+#[serde(transparent)]
+struct UpstreamHeaders(std::collections::HashMap<String, String>);
 
-    match state.client.get(&unpkg_url).send().await {
-        Ok(response) => {
-            if !response.status().is_success() {
-                let status = response.status();
-                error!("[Black Hole] unpkg returned error: {}", status);
-                return (StatusCode::from_u16(status.as_u16()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR), format!("unpkg returned error: {}", status)).into_response();
-            }
-
-            match response.bytes().await {
-                Ok(content) => {
-                    // 创建缓存目录（包括文件的父目录）
-                    if let Some(parent_dir) = cached_file.parent() {
-                        if let Err(e) = async_fs::create_dir_all(parent_dir).await {
-                            warn!("[Black Hole] Failed to create cache directory: {}", e);
-                        }
-                    }
-                    // 保存到缓存
-                    if let Err(e) = async_fs::write(&cached_file, &content).await {
-                        warn!("[Black Hole] Failed to save cache file: {}", e);
-                    }
+impl std::fmt::Debug for UpstreamHeaders {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_map()
+            .entries(self.0.keys().map(|name| (name, "***")))
+            .finish()
+    }
+}
 
-                    let mut headers = HeaderMap::new();
-                    set_content_type(&mut headers, file_path);
-                    
-                    info!("[Black Hole] Successfully downloaded and cached file: {}", file_path);
-                    (StatusCode::OK, headers, content.to_vec()).into_response()
-                }
-                Err(e) => {
-                    error!("[Black Hole] Failed to read response: {}", e);
-                    (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to read response: {}", e)).into_response()
-                }
-            }
-        }
-        Err(e) => {
-            error!("[Black Hole] Download failed: {}", e);
-            (StatusCode::INTERNAL_SERVER_ERROR, format!("Download failed: {}", e)).into_response()
+impl UpstreamHeaders {
+    /// 转换为HeaderMap，值标记为敏感
+    fn to_header_map(&self) -> anyhow::Result<reqwest::header::HeaderMap> {
+        let mut headers = reqwest::header::HeaderMap::new();
+        for (name, value) in &self.0 {
+            let name = reqwest::header::HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| anyhow::anyhow!("Invalid upstream header name: {}", name))?;
+            let mut value = reqwest::header::HeaderValue::from_str(value)
+                .map_err(|_| anyhow::anyhow!("Invalid value for upstream header: {}", name))?;
+            value.set_sensitive(true);
+            headers.insert(name, value);
         }
+        Ok(headers)
     }
 }
 
-fn set_content_type(headers: &mut HeaderMap, file_path: &str) {
-    let path_buf = PathBuf::from(file_path);
-    let ext = path_buf
-        .extension()
-        .and_then(|s| s.to_str())
-        .unwrap_or("");
+#[derive(Debug, Deserialize, Clone)]
+struct LogConfig {
+    enabled: bool,
+    level: String,
+    #[serde(default)]
+    slow_request_ms: u64,
+    #[serde(default)]
+    redact_patterns: Vec<String>,
+    #[serde(default = "default_recent_requests")]
+    recent_requests: usize,
+    #[serde(default)]
+    quiet_downloads: bool,
+}
 
-    let content_type = match ext {
-        "css" => "text/css",
-        "js" => "application/javascript",
-        "json" => "application/json",
-        "html" => "text/html",
-        "png" => "image/png",
-        "jpg" | "jpeg" => "image/jpeg",
-        "gif" => "image/gif",
-        "svg" => "image/svg+xml",
-        _ => "application/octet-stream",
-    };
+fn default_recent_requests() -> usize {
+    100
+}
 
-    headers.insert(
-        axum::http::header::CONTENT_TYPE,
-        content_type.parse().unwrap(),
-    );
+#[derive(Debug, Deserialize, Clone)]
+struct ServerConfig {
+    port: u16,
+    host: String,
+    #[serde(default)]
+    request_timeout_secs: u64,
+    #[serde(default)]
+    wait_for_warmup: bool,
+    #[serde(default)]
+    max_concurrent_requests: usize,
+    #[serde(default)]
+    reload_token: Option<Secret>,
+    #[serde(default = "default_header_read_timeout_secs")]
+    header_read_timeout_secs: u64,
+    #[serde(default = "default_true")]
+    keep_alive: bool,
+    #[serde(default = "default_keep_alive_timeout_secs")]
+    keep_alive_timeout_secs: u64,
+    #[serde(default = "default_shutdown_timeout_secs")]
+    shutdown_timeout_secs: u64,
+    #[serde(default)]
+    worker_threads: usize,
+    #[serde(default = "default_not_found_message")]
+    not_found_message: String,
+    #[serde(default = "default_stream_chunk_bytes")]
+    stream_chunk_bytes: usize,
+    #[serde(default = "default_disk_check_interval_secs")]
+    disk_check_interval_secs: u64,
+    #[serde(default = "default_cache_status_header")]
+    cache_status_header: String,
 }
 
-/// 检查路径是否安全，防止目录遍历攻击
-fn is_safe_path(path: &str) -> bool {
-    // 检查是否包含危险字符
-    if path.contains("..") || path.contains("//") || path.contains('\\') {
-        return false;
-    }
-    
-    // 允许以@开头的scoped npm包名（如@highlightjs/cdn-assets）
-    // 但不允许其他以/开头的绝对路径
-    if path.starts_with('/') && !path.starts_with("@") {
-        return false;
+fn default_keep_alive_timeout_secs() -> u64 {
+    60
+}
+
+fn default_shutdown_timeout_secs() -> u64 {
+    30
+}
+
+fn default_cache_status_header() -> String {
+    "X-Cache".to_string()
+}
+
+fn default_disk_check_interval_secs() -> u64 {
+    10
+}
+
+fn default_stream_chunk_bytes() -> usize {
+    64 * 1024
+}
+
+fn default_not_found_message() -> String {
+    "404 - Not Found".to_string()
+}
+
+fn default_header_read_timeout_secs() -> u64 {
+    30
+}
+
+#[derive(Debug, Deserialize, Clone)]
+struct CompressionConfig {
+    #[serde(default)]
+    enabled: bool,
+    #[serde(default = "default_compression_min_bytes")]
+    min_bytes: usize,
+    #[serde(default = "default_compression_types")]
+    types: Vec<String>,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_bytes: default_compression_min_bytes(),
+            types: default_compression_types(),
+        }
     }
-    
-    // 检查是否为绝对路径
-    if PathBuf::from(path).is_absolute() {
-        return false;
+}
+
+fn default_compression_min_bytes() -> usize {
+    1024
+}
+
+/// 默认只压缩文本类型，图片、字体等已压缩格式跳过
+fn default_compression_types() -> Vec<String> {
+    ["text/", "application/javascript", "application/json", "image/svg+xml", "application/wasm"]
+        .into_iter()
+        .map(String::from)
+        .collect()
+}
+
+impl CompressionConfig {
+    /// 内容类型是否在types允许列表中：含 `/` 的项按Content-Type前缀匹配，其余按扩展名匹配
+    fn is_type_allowed(&self, content_type: &str) -> bool {
+        self.types.iter().any(|entry| {
+            if entry.contains('/') {
+                content_type.starts_with(entry.as_str())
+            } else {
+                let extension = entry.trim_start_matches('.');
+                content_type.starts_with(content_type_for(&format!("file.{}", extension)))
+            }
+        })
     }
-    
-    // 检查路径组件
-    for component in PathBuf::from(path).components() {
-        match component {
-            std::path::Component::ParentDir => return false,
-            std::path::Component::RootDir => return false,
-            std::path::Component::Prefix(_) => return false,
-            _ => {}
+}
+
+#[derive(Debug, Deserialize, Clone)]
+struct CacheControlConfig {
+    #[serde(default = "default_true")]
+    enabled: bool,
+    #[serde(default = "default_cache_control_max_age")]
+    max_age: std::collections::HashMap<String, u64>,
+}
+
+impl Default for CacheControlConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_age: default_cache_control_max_age(),
         }
     }
-    
-    true
 }
 
-/// 验证路径是否在允许的目录范围内
-fn is_path_within_allowed_dirs(target_path: &PathBuf, allowed_dir: &str) -> bool {
-    let allowed_path = match fs::canonicalize(allowed_dir) {
-        Ok(path) => path,
-        Err(_) => return false,
+fn default_cache_control_max_age() -> std::collections::HashMap<String, u64> {
+    [("font/", 31_536_000), ("image/", 2_592_000), ("text/html", 0)]
+        .into_iter()
+        .map(|(prefix, secs)| (prefix.to_string(), secs))
+        .collect()
+}
+
+impl CacheControlConfig {
+    /// 按最长的Content-Type前缀查找max-age
+    fn max_age_for(&self, content_type: &str) -> Option<u64> {
+        self.max_age
+            .iter()
+            .filter(|(prefix, _)| content_type.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, secs)| *secs)
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+struct CharsetConfig {
+    #[serde(default = "default_charset")]
+    default: String,
+    #[serde(default)]
+    types: std::collections::HashMap<String, String>,
+    #[serde(default)]
+    paths: std::collections::HashMap<String, String>,
+}
+
+impl Default for CharsetConfig {
+    fn default() -> Self {
+        Self {
+            default: default_charset(),
+            types: Default::default(),
+            paths: Default::default(),
+        }
+    }
+}
+
+fn default_charset() -> String {
+    "utf-8".to_string()
+}
+
+impl CharsetConfig {
+    /// 查找文件应使用的charset：按最长路径前缀匹配，其次按Content-Type匹配，
+    /// 最后文本类型使用默认值；返回None或空字符串时不附加charset
+    fn charset_for(&self, content_type: &str, file_path: &str) -> Option<&str> {
+        let is_text = is_text_content_type(content_type);
+        let by_path = self
+            .paths
+            .iter()
+            .filter(|(prefix, _)| file_path.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, charset)| charset.as_str());
+        let charset = match (by_path, self.types.get(content_type)) {
+            (Some(charset), _) if is_text => charset,
+            (_, Some(charset)) => charset.as_str(),
+            _ if is_text => self.default.as_str(),
+            _ => return None,
+        };
+        (!charset.is_empty()).then_some(charset)
+    }
+}
+
+/// 需要声明字符编码的文本类型
+fn is_text_content_type(content_type: &str) -> bool {
+    content_type.starts_with("text/")
+        || content_type == "application/javascript"
+        || content_type == "application/json"
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+struct AdminConfig {
+    #[serde(default)]
+    token: Option<Secret>,
+}
+
+/// 仅用于本地韧性测试的故障注入，切勿在生产环境开启
+#[derive(Debug, Deserialize, Clone, Default)]
+struct ChaosConfig {
+    #[serde(default)]
+    enabled: bool,
+    #[serde(default)]
+    delay_ms: u64,
+    #[serde(default)]
+    error_rate: f64,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+struct MetricsConfig {
+    #[serde(default)]
+    persist: bool,
+    #[serde(default = "default_metrics_persist_interval_secs")]
+    persist_interval_secs: u64,
+    #[serde(default = "default_metrics_latency_buckets")]
+    latency_buckets: Vec<f64>,
+    #[serde(default)]
+    hit_ratio_log_interval_secs: u64,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            persist: false,
+            persist_interval_secs: default_metrics_persist_interval_secs(),
+            latency_buckets: default_metrics_latency_buckets(),
+            hit_ratio_log_interval_secs: 0,
+        }
+    }
+}
+
+fn default_metrics_persist_interval_secs() -> u64 {
+    60
+}
+
+fn default_metrics_latency_buckets() -> Vec<f64> {
+    vec![0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0]
+}
+
+#[derive(Debug, Deserialize, Clone)]
+struct UiConfig {
+    #[serde(default = "default_ui_dir")]
+    dir: String,
+    #[serde(default)]
+    favicon: Option<String>,
+    #[serde(default)]
+    template: bool,
+    #[serde(default)]
+    base_url: Option<String>,
+    #[serde(default)]
+    template_vars: std::collections::HashMap<String, String>,
+    #[serde(default = "default_robots_txt")]
+    robots_txt: String,
+    #[serde(default = "default_true")]
+    index_aliases: bool,
+    #[serde(default = "default_index_files")]
+    index_files: Vec<String>,
+    #[serde(default)]
+    preload_links: Vec<String>,
+}
+
+impl Default for UiConfig {
+    fn default() -> Self {
+        Self {
+            dir: default_ui_dir(),
+            favicon: None,
+            template: false,
+            base_url: None,
+            template_vars: Default::default(),
+            robots_txt: default_robots_txt(),
+            index_aliases: true,
+            index_files: default_index_files(),
+            preload_links: Vec::new(),
+        }
+    }
+}
+
+fn default_ui_dir() -> String {
+    "ui".to_string()
+}
+
+fn default_index_files() -> Vec<String> {
+    vec!["index.html".to_string()]
+}
+
+fn default_robots_txt() -> String {
+    "User-agent: *\nDisallow: /\n".to_string()
+}
+
+#[derive(Clone)]
+struct AppState {
+    config: Config,
+    client: reqwest::Client,
+    unpkg_regex: Regex,
+    index_cache: std::sync::Arc<tokio::sync::RwLock<Option<std::sync::Arc<CachedIndex>>>>,
+    metrics: std::sync::Arc<metrics::Metrics>,
+    tag_cache: resolve::TagCache,
+    cache_locks: std::sync::Arc<locks::CacheLocks>,
+    resolver: std::sync::Arc<dyn upstream::UpstreamResolver>,
+    /// 预热完成前为false，/healthz在server.wait_for_warmup开启时据此返回503
+    ready: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    redactor: redact::Redactor,
+    /// 编译后的proxy.no_cache_patterns
+    no_cache_patterns: std::sync::Arc<Vec<Regex>>,
+    request_log: std::sync::Arc<request_log::RequestLog>,
+    /// 启动时使用的配置文件路径，/reload重新读取时使用
+    config_path: std::sync::Arc<str>,
+    access: std::sync::Arc<access::AccessTracker>,
+    micro_cache: std::sync::Arc<micro_cache::MicroCache>,
+    disk_health: std::sync::Arc<disk_health::DiskHealth>,
+    host_limiter: std::sync::Arc<host_limit::HostLimiter>,
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = parse_args(std::env::args().skip(1))?;
+
+    // 加载配置文件；运行时的线程数由配置决定，因此先在单线程运行时中加载
+    let loaded = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?
+        .block_on(load_config(&args.config_path));
+    let config = match loaded {
+        Ok(config) => config,
+        Err(e) if args.check_config => {
+            eprintln!("Failed to load {}: {}", args.config_path, e);
+            std::process::exit(1);
+        }
+        Err(e) => return Err(e),
     };
-    
-    let target_canonical = match target_path.canonicalize() {
-        Ok(path) => path,
-        Err(_) => {
-            // 如果文件不存在，检查其父目录
-            let parent = target_path.parent().unwrap_or(target_path);
-            match parent.canonicalize() {
-                Ok(path) => path,
-                Err(_) => return false,
+
+    // 仅校验配置，不绑定端口、不创建目录
+    if args.check_config {
+        let problems = config.validate();
+        if problems.is_empty() {
+            println!("Configuration OK");
+            std::process::exit(0);
+        }
+        for problem in &problems {
+            eprintln!("{}", problem);
+        }
+        std::process::exit(1);
+    }
+
+    let mut runtime = tokio::runtime::Builder::new_multi_thread();
+    runtime.enable_all();
+    if config.server.worker_threads > 0 {
+        runtime.worker_threads(config.server.worker_threads);
+    }
+    runtime.build()?.block_on(run(args, config))
+}
+
+async fn run(args: CliArgs, config: Config) -> anyhow::Result<()> {
+    // 初始化日志
+    if config.log.enabled {
+        let level = match config.log.level.as_str() {
+            "trace" => tracing::Level::TRACE,
+            "debug" => tracing::Level::DEBUG,
+            "info" => tracing::Level::INFO,
+            "warn" => tracing::Level::WARN,
+            "error" => tracing::Level::ERROR,
+            _ => tracing::Level::INFO,
+        };
+        
+        // 无效的log.redact_patterns由build_state报错退出
+        let redactor = redact::Redactor::new(&config.log.redact_patterns).unwrap_or_default();
+        tracing_subscriber::fmt()
+            .with_max_level(level)
+            .with_writer(redact::RedactingMakeWriter::new(redactor, std::io::stdout))
+            .init();
+            
+        info!("[Black Hole] Configuration loaded successfully: {:?}", config);
+    }
+
+    for problem in config.validate() {
+        warn!("[Black Hole] Configuration problem: {}", problem);
+    }
+
+    if config.chaos.enabled {
+        warn!(
+            "[Black Hole] Chaos testing is ENABLED: {} ms delay, {} error rate on proxied and static files; never use this in production",
+            config.chaos.delay_ms, config.chaos.error_rate
+        );
+    }
+
+    // 创建必要的目录
+    create_dirs(&config).await?;
+
+    // 初始化计数器，启用持久化时从状态文件恢复
+    let metrics = std::sync::Arc::new(metrics::Metrics::new(config.metrics.latency_buckets.clone()));
+    let metrics_state_file = metrics::state_file_path(&config.proxy.cache_dir);
+    if config.metrics.persist {
+        metrics::load(&metrics, &metrics_state_file).await;
+
+        let metrics = metrics.clone();
+        let path = metrics_state_file.clone();
+        let interval_secs = config.metrics.persist_interval_secs.max(1);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(interval_secs));
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                metrics::save(&metrics, &path).await;
+            }
+        });
+    }
+
+    // 定期在日志中输出缓存命中率
+    metrics::spawn_hit_ratio_logger(metrics.clone(), config.metrics.hit_ratio_log_interval_secs);
+
+    // 创建应用状态
+    let state = build_state(&config, &args.config_path, metrics.clone())?;
+
+    // 定期清理过期的缓存文件
+    eviction::spawn_cleanup_task(config.proxy.clone(), state.cache_locks.clone());
+    let client = state.client.clone();
+    let upstream_headers = config.proxy.upstream_headers.to_header_map()?;
+
+    // 定期保存缓存文件的最后访问时间
+    access::spawn_flush_task(
+        state.access.clone(),
+        state.cache_locks.clone(),
+        config.proxy.cache_dir.clone(),
+        config.proxy.access_flush_interval_secs,
+    );
+
+    // 后台预热缓存，完成后标记为就绪
+    tokio::spawn(prefetch::warmup(state.clone()));
+
+    let content_routes = content_routes(&config);
+
+    // 创建路由
+    let app = Router::new()
+        .merge(content_routes)
+        .route("/", get(handle_index).head(handle_index_head))
+        .route("/index.html", get(handle_index_alias).head(handle_index_alias_head))
+        .route("/favicon.ico", get(handle_favicon))
+        .route("/robots.txt", get(handle_robots_txt))
+        .route("/stats", get(handle_stats))
+        .route("/healthz", get(handle_healthz))
+        .route("/healthz/disk", get(disk_health::handle_healthz_disk))
+        .route("/metrics", get(handle_metrics))
+        .route("/cache/info/*path", get(cache_info::handle_cache_info))
+        .merge(admin_routes(&state))
+        // 重载在未配置admin.token时还可以使用server.reload_token，由处理函数自行鉴权
+        .route("/reload", post(reload::handle_reload))
+        .fallback(handle_fallback)
+        .layer(axum::middleware::from_fn_with_state(state.clone(), middleware::log_slow_requests))
+        .layer(axum::middleware::from_fn_with_state(state.clone(), middleware::record_recent_requests))
+        .with_state(state)
+        .layer(
+            // 整个请求处理的超时兜底，0表示不限制
+            ServiceBuilder::new()
+                .layer(HandleErrorLayer::new(handle_middleware_error))
+                .option_layer(
+                    (config.server.request_timeout_secs > 0)
+                        .then(|| TimeoutLayer::new(Duration::from_secs(config.server.request_timeout_secs))),
+                ),
+        )
+        .layer(TraceLayer::new_for_http().make_span_with(move |request: &axum::extract::Request| {
+            tracing::debug_span!(
+                "request",
+                method = %request.method(),
+                uri = %request.uri(),
+                version = ?request.version(),
+            )
+        }));
+    // 路径改写必须在路由匹配之前进行，因此包在Router外层
+    let app = tower::Layer::layer(
+        &axum::middleware::from_fn_with_state(config.ui.index_aliases, middleware::normalize_leading_slashes),
+        app,
+    );
+    // 全局并发上限，超出时立即返回503而不是排队等待；
+    // Router::layer会为每个路由单独创建限制，因此同样包在Router外层
+    let app = tower::Layer::layer(
+        &ServiceBuilder::new()
+            .layer(HandleErrorLayer::new(handle_middleware_error))
+            .option_layer((config.server.max_concurrent_requests > 0).then(|| {
+                ServiceBuilder::new()
+                    .load_shed()
+                    .concurrency_limit(config.server.max_concurrent_requests)
+            })),
+        app,
+    );
+
+    // 启动服务器
+    let addr = format!("{}:{}", config.server.host, config.server.port);
+    info!("[Black Hole] Starting server");
+    info!("[Black Hole] Server started at http://{}", addr);
+    info!("[Black Hole] Proxy feature status: {}", config.proxy.enabled);
+    if config.proxy.offline {
+        info!("[Black Hole] Offline mode: serving only cached and mirrored files, upstream will never be contacted");
+    }
+
+    let listener = tokio::net::TcpListener::bind(&addr).await?;
+
+    // 启动后探测上游是否可达，只记录日志不影响启动
+    if config.proxy.enabled && config.proxy.startup_probe && !config.proxy.offline {
+        tokio::spawn(probe_upstream(client.clone(), upstream_headers.clone(), config.proxy.startup_probe_path.clone()));
+    }
+    server::serve(listener, app, &config.server, shutdown_signal()).await?;
+
+    // 优雅退出时写入最终的计数器
+    if config.metrics.persist {
+        metrics::save(&metrics, &metrics_state_file).await;
+        info!("[Black Hole] Metrics flushed to {:?}", metrics_state_file);
+    }
+
+    Ok(())
+}
+
+/// 代理和本地静态文件的路由，前缀相同时共用一个路由并按unpkg格式区分
+fn content_routes(config: &Config) -> Router<AppState> {
+    let proxy_prefix = config.proxy.proxy_route_prefix();
+    let static_prefix = config.proxy.static_route_prefix();
+    let mut routes = if proxy_prefix == static_prefix {
+        Router::new().route(
+            &format!("{}/*path", proxy_prefix),
+            get(handle_static_request).options(handle_content_options),
+        )
+    } else {
+        Router::new()
+            .route(
+                &format!("{}/*path", proxy_prefix),
+                get(handle_proxy_request).options(handle_content_options),
+            )
+            .route(
+                &format!("{}/*path", static_prefix),
+                get(handle_local_static_route).options(handle_content_options),
+            )
+    };
+    // 额外挂载的静态目录，每个挂载一个路由，共用本地静态文件的处理逻辑；前缀冲突的挂载跳过（axum不允许重复路由）
+    let mut mounted_prefixes = vec![proxy_prefix.clone(), static_prefix.clone()];
+    for mount in &config.proxy.static_mounts {
+        let prefix = mount.route_prefix();
+        if prefix == "/" || mounted_prefixes.contains(&prefix) {
+            warn!("[Black Hole] Skipping static mount {} for {}: prefix already in use", prefix, mount.dir);
+            continue;
+        }
+        mounted_prefixes.push(prefix.clone());
+        let static_dir: std::sync::Arc<str> = mount.dir.as_str().into();
+        routes = routes.route(
+            &format!("{}/*path", prefix),
+            get(move |path: Path<String>, state: State<AppState>, uri: axum::http::Uri, request_headers: HeaderMap| {
+                handle_static_dir_route(path, state, uri, request_headers, static_dir.clone())
+            })
+            .options(handle_content_options),
+        );
+        info!("[Black Hole] Serving {} from {}", prefix, mount.dir);
+    }
+    routes
+}
+
+/// 管理接口，需要admin.token鉴权，未配置admin.token时关闭
+fn admin_routes(state: &AppState) -> Router<AppState> {
+    Router::new()
+        .route("/cache/prefetch", post(prefetch::handle_cache_prefetch))
+        .route("/cache/gc", post(eviction::handle_cache_gc))
+        .route("/cache/verify", post(verify::handle_cache_verify))
+        // 需要读取并计算整个缓存的摘要，开销较大
+        .route("/cache/manifest", get(manifest::handle_cache_manifest))
+        // 会遍历整个缓存目录，且暴露缓存了哪些包
+        .route("/cache/search", get(cache_search::handle_cache_search))
+        // 最近请求的路径和来源地址
+        .route("/logs/recent", get(request_log::handle_recent_logs))
+        // 会遍历整个缓存目录并读取所有元数据
+        .route("/stats/coldest", get(access::handle_coldest))
+        .route_layer(axum::middleware::from_fn_with_state(state.clone(), middleware::require_admin_token))
+}
+
+/// 根据配置创建应用状态：HTTP客户端、上游解析器、日志脱敏和no_cache_patterns等
+fn build_state(config: &Config, config_path: &str, metrics: std::sync::Arc<metrics::Metrics>) -> anyhow::Result<AppState> {
+    // 创建HTTP客户端
+    let client = build_client(&config.proxy)?;
+    let upstream_headers = config.proxy.upstream_headers.to_header_map()?;
+
+    // 编译日志脱敏规则
+    let redactor = redact::Redactor::new(&config.log.redact_patterns)
+        .map_err(|e| anyhow::anyhow!("Invalid log.redact_patterns: {}", e))?;
+
+    let no_cache_patterns = config
+        .proxy
+        .no_cache_patterns
+        .iter()
+        .map(|glob| {
+            cache_search::glob_to_regex(glob)
+                .ok_or_else(|| anyhow::anyhow!("Invalid proxy.no_cache_patterns entry: {}", glob))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    // 编译正则表达式，匹配代理前缀之后的 `包名@版本/文件`，支持scoped packages（@开头的包名）
+    let unpkg_regex = Regex::new(r"^(@?[^@/]+(?:/[^@/]+)?)@([^/]+)/(.+)$")?;
+
+    Ok(AppState {
+        config: config.clone(),
+        client: client.clone(),
+        unpkg_regex,
+        index_cache: std::sync::Arc::new(tokio::sync::RwLock::new(None)),
+        metrics,
+        tag_cache: Default::default(),
+        cache_locks: std::sync::Arc::new(locks::CacheLocks::new(config.proxy.cache_lock_shards)),
+        resolver: std::sync::Arc::new(upstream::UnpkgResolver::new(
+            client.clone(),
+            upstream_headers,
+            UNPKG_URL,
+            config.proxy.redirect_passthrough,
+            config.proxy.transparent_upstream_errors,
+        )),
+        ready: Default::default(),
+        redactor,
+        no_cache_patterns: std::sync::Arc::new(no_cache_patterns),
+        request_log: std::sync::Arc::new(request_log::RequestLog::new(config.log.recent_requests)),
+        config_path: config_path.into(),
+        access: Default::default(),
+        micro_cache: Default::default(),
+        disk_health: Default::default(),
+        host_limiter: std::sync::Arc::new(host_limit::HostLimiter::new(config.proxy.max_connections_per_host)),
+    })
+}
+
+/// 按配置创建访问上游的HTTP客户端
+fn build_client(config: &ProxyConfig) -> anyhow::Result<reqwest::Client> {
+    let mut builder = reqwest::Client::builder();
+    // 连接超时让不可达的上游尽快失败，请求超时限制整个下载（包括读取响应体）的时间
+    if config.connect_timeout_secs > 0 {
+        builder = builder.connect_timeout(Duration::from_secs(config.connect_timeout_secs));
+    }
+    if config.request_timeout_secs > 0 {
+        builder = builder.timeout(Duration::from_secs(config.request_timeout_secs));
+    }
+    // 透传重定向时不跟随，由客户端自行请求新地址
+    let redirect_policy = if config.redirect_passthrough || config.max_redirects == 0 {
+        reqwest::redirect::Policy::none()
+    } else {
+        reqwest::redirect::Policy::limited(config.max_redirects)
+    };
+    builder = builder.redirect(redirect_policy);
+    // 空闲连接池同样按主机限制，并发请求数由HostLimiter控制
+    if config.max_connections_per_host > 0 {
+        builder = builder.pool_max_idle_per_host(config.max_connections_per_host);
+    }
+    // 额外信任私有CA签发的证书，系统根证书仍然有效
+    if let Some(ca_cert_path) = &config.ca_cert_path {
+        let content = fs::read(ca_cert_path)
+            .map_err(|e| anyhow::anyhow!("Failed to read proxy.ca_cert_path '{}': {}", ca_cert_path, e))?;
+        let certificate = reqwest::Certificate::from_pem(&content)
+            .or_else(|_| reqwest::Certificate::from_der(&content))
+            .map_err(|e| anyhow::anyhow!("Invalid certificate in proxy.ca_cert_path '{}': {}", ca_cert_path, e))?;
+        builder = builder.add_root_certificate(certificate);
+    }
+    if config.danger_accept_invalid_certs {
+        warn!("[Black Hole] TLS certificate verification for upstream is DISABLED (danger_accept_invalid_certs)");
+        builder = builder.danger_accept_invalid_certs(true);
+    }
+    Ok(builder.build()?)
+}
+
+/// 请求一个已知的小文件，检查DNS和出口网络配置
+async fn probe_upstream(client: reqwest::Client, upstream_headers: reqwest::header::HeaderMap, probe_path: String) {
+    let url = format!("{}/{}", UNPKG_URL, probe_path.trim_start_matches('/'));
+    let started = std::time::Instant::now();
+    match client.get(&url).headers(upstream_headers).send().await {
+        Ok(response) if response.status().is_success() => {
+            info!("[Black Hole] Upstream probe succeeded: {} ({} ms)", url, started.elapsed().as_millis());
+        }
+        Ok(response) => {
+            warn!("[Black Hole] Upstream probe returned {}: {}", response.status(), url);
+        }
+        Err(e) => {
+            warn!("[Black Hole] Upstream probe failed: {}: {}", url, e);
+        }
+    }
+}
+
+/// 将中间件错误（如超时）转换为响应
+async fn handle_middleware_error(err: tower::BoxError) -> Response {
+    if err.is::<tower::timeout::error::Elapsed>() {
+        warn!("[Black Hole] Request timed out");
+        return (StatusCode::GATEWAY_TIMEOUT, "Request timed out").into_response();
+    }
+    if err.is::<tower::load_shed::error::Overloaded>() {
+        warn!("[Black Hole] Concurrency limit reached, rejecting request");
+        return (StatusCode::SERVICE_UNAVAILABLE, "Server is busy, please retry later").into_response();
+    }
+    error!("[Black Hole] Unhandled middleware error: {}", err);
+    (StatusCode::INTERNAL_SERVER_ERROR, format!("Internal error: {}", err)).into_response()
+}
+
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("[Black Hole] Failed to listen for shutdown signal: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+    // systemd、docker stop等发送的是SIGTERM
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                error!("[Black Hole] Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
             }
         }
     };
-    
-    target_canonical.starts_with(&allowed_path)
-}
\ No newline at end of file
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+    info!("[Black Hole] Shutdown signal received");
+}
+
+/// 命令行参数
+struct CliArgs {
+    config_path: String,
+    check_config: bool,
+}
+
+fn parse_args(args: impl Iterator<Item = String>) -> anyhow::Result<CliArgs> {
+    let mut cli = CliArgs {
+        config_path: "config.toml".to_string(),
+        check_config: false,
+    };
+    let mut args = args;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--check-config" => cli.check_config = true,
+            "--config" => {
+                cli.config_path = args
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("--config requires a path (or - for stdin)"))?;
+            }
+            other => anyhow::bail!("Unknown argument: {}", other),
+        }
+    }
+    Ok(cli)
+}
+
+/// 表示从标准输入读取配置的路径
+const STDIN_CONFIG_PATH: &str = "-";
+
+async fn load_config(filename: &str) -> anyhow::Result<Config> {
+    let content = if filename == STDIN_CONFIG_PATH {
+        let mut content = String::new();
+        tokio::io::AsyncReadExt::read_to_string(&mut tokio::io::stdin(), &mut content).await?;
+        content
+    } else {
+        async_fs::read_to_string(filename).await?
+    };
+    // 按扩展名选择解析器，统一转换为JSON值后再展开环境变量并反序列化；标准输入按TOML解析
+    let extension = std::path::Path::new(filename)
+        .extension()
+        .and_then(|s| s.to_str())
+        .unwrap_or("toml")
+        .to_ascii_lowercase();
+    let mut value: serde_json::Value = match extension.as_str() {
+        "yaml" | "yml" => serde_yaml::from_str(&content)?,
+        "json" => serde_json::from_str(&content)?,
+        _ => toml::from_str(&content)?,
+    };
+    expand_env_vars(&mut value)?;
+    let config: Config = serde_json::from_value(value)?;
+    Ok(config)
+}
+
+/// 展开配置中所有字符串值里的 `${VAR}` 环境变量引用，变量未设置时报错
+fn expand_env_vars(value: &mut serde_json::Value) -> anyhow::Result<()> {
+    let pattern = Regex::new(r"\$\{([A-Za-z_][A-Za-z0-9_]*)\}")?;
+    expand_env_vars_with(value, &pattern)
+}
+
+fn expand_env_vars_with(value: &mut serde_json::Value, pattern: &Regex) -> anyhow::Result<()> {
+    match value {
+        serde_json::Value::String(text) => {
+            if !pattern.is_match(text) {
+                return Ok(());
+            }
+            let mut expanded = String::with_capacity(text.len());
+            let mut last = 0;
+            for captures in pattern.captures_iter(text) {
+                let whole = captures.get(0).unwrap();
+                let name = &captures[1];
+                let var = std::env::var(name).map_err(|_| {
+                    anyhow::anyhow!("Environment variable '{}' referenced in config is not set", name)
+                })?;
+                expanded.push_str(&text[last..whole.start()]);
+                expanded.push_str(&var);
+                last = whole.end();
+            }
+            expanded.push_str(&text[last..]);
+            *text = expanded;
+        }
+        serde_json::Value::Array(items) => {
+            for item in items {
+                expand_env_vars_with(item, pattern)?;
+            }
+        }
+        serde_json::Value::Object(map) => {
+            for (_, item) in map.iter_mut() {
+                expand_env_vars_with(item, pattern)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+async fn create_dirs(config: &Config) -> anyhow::Result<()> {
+    for (name, dir) in config.proxy.local_dirs() {
+        // 路径已存在但不是目录时给出明确的错误，而不是create_dir_all的系统错误
+        if let Ok(metadata) = async_fs::metadata(dir).await
+            && !metadata.is_dir()
+        {
+            anyhow::bail!("{} '{}' exists but is not a directory", name, dir);
+        }
+        // 目录由部署环境预先创建时只检查是否存在
+        if !config.proxy.create_dirs_on_startup {
+            if async_fs::metadata(dir).await.is_err() {
+                anyhow::bail!(
+                    "{} '{}' does not exist and create_dirs_on_startup is disabled",
+                    name,
+                    dir
+                );
+            }
+            continue;
+        }
+        async_fs::create_dir_all(dir)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to create {} '{}': {}", name, dir, e))?;
+    }
+    if let Some(mode) = config.proxy.cache_dir_mode {
+        permissions::set_mode(std::path::Path::new(&config.proxy.cache_dir), mode).await;
+    }
+    Ok(())
+}
+
+async fn handle_index(State(state): State<AppState>, request_headers: HeaderMap) -> impl IntoResponse {
+    match load_index(&state).await {
+        Ok(index) => {
+            let (mut headers, body) = index.response_parts(&request_headers);
+            set_cache_control(&state.config.cache_control, &mut headers);
+            set_preload_links(&state.config.ui, &mut headers);
+            (StatusCode::OK, headers, body).into_response()
+        }
+        Err(response) => response,
+    }
+}
+
+/// HEAD请求返回与GET一致的Content-Type和Content-Length，不返回内容
+async fn handle_index_head(State(state): State<AppState>, request_headers: HeaderMap) -> impl IntoResponse {
+    match load_index(&state).await {
+        Ok(index) => {
+            let (mut headers, body) = index.response_parts(&request_headers);
+            set_cache_control(&state.config.cache_control, &mut headers);
+            set_preload_links(&state.config.ui, &mut headers);
+            headers.insert(axum::http::header::CONTENT_LENGTH, body.len().into());
+            (StatusCode::OK, headers).into_response()
+        }
+        Err(response) => response,
+    }
+}
+
+/// `/index.html` 与 `/` 返回相同内容，关闭ui.index_aliases时返回404
+async fn handle_index_alias(state: State<AppState>, request_headers: HeaderMap) -> Response {
+    if !state.config.ui.index_aliases {
+        return handle_fallback(state, request_headers).await;
+    }
+    handle_index(state, request_headers).await.into_response()
+}
+
+async fn handle_index_alias_head(state: State<AppState>, request_headers: HeaderMap) -> Response {
+    if !state.config.ui.index_aliases {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    handle_index_head(state, request_headers).await.into_response()
+}
+
+/// 首页响应附加 `Link: <...>; rel=preload`，让浏览器尽早开始下载关键资源
+fn set_preload_links(config: &UiConfig, headers: &mut HeaderMap) {
+    for href in &config.preload_links {
+        if let Ok(value) = axum::http::HeaderValue::from_str(&preload_link(href)) {
+            headers.append(axum::http::header::LINK, value);
+        }
+    }
+}
+
+/// 按扩展名推断preload的 `as` 类型，字体和fetch按规范需要crossorigin
+fn preload_link(href: &str) -> String {
+    let extension = std::path::Path::new(href.split(['?', '#']).next().unwrap_or(href))
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_ascii_lowercase);
+    let destination = match extension.as_deref() {
+        Some("js" | "mjs") => Some("script"),
+        Some("css") => Some("style"),
+        Some("woff" | "woff2" | "ttf" | "otf") => Some("font"),
+        Some("png" | "jpg" | "jpeg" | "gif" | "svg" | "webp" | "avif" | "ico") => Some("image"),
+        Some("json") => Some("fetch"),
+        _ => None,
+    };
+    match destination {
+        Some(destination @ ("font" | "fetch")) => format!("<{}>; rel=preload; as={}; crossorigin", href, destination),
+        Some(destination) => format!("<{}>; rel=preload; as={}", href, destination),
+        None => format!("<{}>; rel=preload", href),
+    }
+}
+
+/// 未匹配任何路由时返回统一的404，浏览器得到HTML页面，其他客户端按Accept返回文本或JSON
+async fn handle_fallback(State(state): State<AppState>, request_headers: HeaderMap) -> Response {
+    errors::not_found_response(&request_headers, &state.config.server.not_found_message)
+}
+
+fn index_headers() -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        axum::http::header::CONTENT_TYPE,
+        "text/html; charset=utf-8".parse().unwrap(),
+    );
+    headers
+}
+
+/// 内存中缓存的index.html，同时保存预先压缩好的版本，避免每次请求重新压缩
+#[derive(Debug)]
+struct CachedIndex {
+    raw: axum::body::Bytes,
+    gzip: Option<axum::body::Bytes>,
+    brotli: Option<axum::body::Bytes>,
+}
+
+impl CachedIndex {
+    /// 按compression配置生成压缩版本，未启用或内容过小时只保存原始内容
+    fn new(content: String, config: &CompressionConfig) -> Self {
+        let raw = axum::body::Bytes::from(content);
+        let compress = |encoding| {
+            if !config.enabled || raw.len() < config.min_bytes || !config.is_type_allowed("text/html") {
+                return None;
+            }
+            match compression::compress(&raw, encoding) {
+                Ok(compressed) => Some(axum::body::Bytes::from(compressed)),
+                Err(e) => {
+                    warn!("[Black Hole] Failed to compress index.html: {}", e);
+                    None
+                }
+            }
+        };
+        Self {
+            gzip: compress(compression::Encoding::Gzip),
+            brotli: compress(compression::Encoding::Brotli),
+            raw,
+        }
+    }
+
+    /// 根据Accept-Encoding选择响应内容和对应的响应头
+    fn response_parts(&self, request_headers: &HeaderMap) -> (HeaderMap, axum::body::Bytes) {
+        let mut headers = index_headers();
+        if self.gzip.is_some() || self.brotli.is_some() {
+            compression::set_vary_accept_encoding(&mut headers);
+        }
+        let variant = compression::choose_encoding(request_headers).and_then(|encoding| {
+            let body = match encoding {
+                compression::Encoding::Gzip => self.gzip.as_ref(),
+                compression::Encoding::Brotli => self.brotli.as_ref(),
+            };
+            body.map(|body| (encoding, body.clone()))
+        });
+        match variant {
+            Some((encoding, body)) => {
+                headers.insert(
+                    axum::http::header::CONTENT_ENCODING,
+                    axum::http::HeaderValue::from_static(encoding.as_str()),
+                );
+                (headers, body)
+            }
+            None => (headers, self.raw.clone()),
+        }
+    }
+}
+
+/// 读取index.html，优先使用内存缓存
+async fn load_index(state: &AppState) -> Result<std::sync::Arc<CachedIndex>, Response> {
+    // 首先检查缓存
+    {
+        let cache = state.index_cache.read().await;
+        if let Some(cached_content) = cache.as_ref() {
+            info!("[Black Hole] Using cached index.html");
+            return Ok(cached_content.clone());
+        }
+    }
+
+    // 缓存中没有，按ui.index_files的顺序读取第一个存在的文件
+    for name in &state.config.ui.index_files {
+        let index_path = PathBuf::from(&state.config.ui.dir).join(name);
+        info!("[Black Hole] Reading index file: {:?}", index_path);
+
+        let content = match async_fs::read_to_string(&index_path).await {
+            Ok(content) => content,
+            Err(e) => {
+                warn!("[Black Hole] Failed to read index file {:?}: {}", index_path, e);
+                continue;
+            }
+        };
+        let content = if state.config.ui.template {
+            render_index_template(&state.config, &content)
+        } else {
+            content
+        };
+        let index = std::sync::Arc::new(CachedIndex::new(content, &state.config.compression));
+
+        // 将内容存入缓存
+        {
+            let mut cache = state.index_cache.write().await;
+            *cache = Some(index.clone());
+        }
+
+        info!("[Black Hole] Successfully read and cached index file: {:?}", index_path);
+        return Ok(index);
+    }
+
+    error!("[Black Hole] No index file found in {} (tried {:?})", state.config.ui.dir, state.config.ui.index_files);
+    Err((StatusCode::NOT_FOUND, "404 - index file not found").into_response())
+}
+
+/// 替换index.html中的 `{{base_url}}`、`{{version}}` 和template_vars中的变量
+fn render_index_template(config: &Config, content: &str) -> String {
+    let base_url = config
+        .ui
+        .base_url
+        .clone()
+        .unwrap_or_else(|| format!("http://{}:{}", config.server.host, config.server.port));
+
+    let mut rendered = content
+        .replace("{{base_url}}", &base_url)
+        .replace("{{version}}", env!("CARGO_PKG_VERSION"));
+    for (name, value) in &config.ui.template_vars {
+        rendered = rendered.replace(&format!("{{{{{}}}}}", name), value);
+    }
+    rendered
+}
+
+/// 提供UI目录下配置的favicon，未配置时返回204避免404日志
+async fn handle_favicon(State(state): State<AppState>) -> Response {
+    let Some(favicon) = &state.config.ui.favicon else {
+        return StatusCode::NO_CONTENT.into_response();
+    };
+
+    let favicon_path = PathBuf::from(&state.config.ui.dir).join(favicon);
+    match async_fs::read(&favicon_path).await {
+        Ok(content) => {
+            let mut headers = HeaderMap::new();
+            set_content_type(&state.config.charset, &mut headers, favicon);
+            set_cache_control(&state.config.cache_control, &mut headers);
+            (StatusCode::OK, headers, content).into_response()
+        }
+        Err(e) => {
+            warn!("[Black Hole] Failed to read favicon {:?}: {}", favicon_path, e);
+            (StatusCode::NOT_FOUND, "404 - favicon not found").into_response()
+        }
+    }
+}
+
+async fn handle_robots_txt(State(state): State<AppState>) -> impl IntoResponse {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        axum::http::header::CONTENT_TYPE,
+        "text/plain; charset=utf-8".parse().unwrap(),
+    );
+    (StatusCode::OK, headers, state.config.ui.robots_txt.clone())
+}
+
+/// 健康检查；开启server.wait_for_warmup时，缓存预热完成前返回503
+async fn handle_healthz(State(state): State<AppState>) -> impl IntoResponse {
+    if state.config.server.wait_for_warmup && !state.ready.load(std::sync::atomic::Ordering::Relaxed) {
+        return (StatusCode::SERVICE_UNAVAILABLE, "warming up");
+    }
+    (StatusCode::OK, "ok")
+}
+
+async fn handle_stats(State(state): State<AppState>) -> impl IntoResponse {
+    axum::Json(state.metrics.snapshot())
+}
+
+async fn handle_metrics(State(state): State<AppState>) -> impl IntoResponse {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        axum::http::header::CONTENT_TYPE,
+        "text/plain; version=0.0.4".parse().unwrap(),
+    );
+    (StatusCode::OK, headers, state.metrics.render_prometheus())
+}
+
+/// 文件路由的OPTIONS请求，返回支持的方法
+async fn handle_content_options() -> Response {
+    (
+        StatusCode::NO_CONTENT,
+        [(axum::http::header::ALLOW, "GET, HEAD, OPTIONS")],
+    )
+        .into_response()
+}
+
+/// proxy_prefix与static_prefix相同时共用的路由，按是否为unpkg格式分发
+async fn handle_static_request(
+    Path(path): Path<String>,
+    State(state): State<AppState>,
+    query: axum::extract::RawQuery,
+    uri: axum::http::Uri,
+    request_headers: HeaderMap,
+) -> Response {
+    if state.unpkg_regex.is_match(&path) {
+        handle_proxy_request(Path(path), State(state), query, uri, request_headers).await
+    } else {
+        handle_local_static_route(Path(path), State(state), uri, request_headers).await
+    }
+}
+
+/// 处理 `<proxy_prefix>/:package@:version/*file`
+async fn handle_proxy_request(
+    Path(path): Path<String>,
+    State(state): State<AppState>,
+    axum::extract::RawQuery(query): axum::extract::RawQuery,
+    uri: axum::http::Uri,
+    request_headers: HeaderMap,
+) -> Response {
+    info!("[Black Hole] Received request: {}", uri.path());
+
+    if let Some(response) = chaos::inject(&state.config.chaos).await {
+        return response;
+    }
+
+    let Some(captures) = state.unpkg_regex.captures(&path) else {
+        return errors::error_response(&request_headers, StatusCode::NOT_FOUND, "Expected package@version/file");
+    };
+    let package_name = captures.get(1).unwrap().as_str();
+    let version = captures.get(2).unwrap().as_str();
+    let file_path = captures.get(3).unwrap().as_str();
+
+    let bypass_cache = state.config.proxy.wants_cache_bypass(query.as_deref());
+    handle_unpkg_request(&state, &request_headers, package_name, version, file_path, bypass_cache).await
+}
+
+/// 处理 `<static_prefix>/*file`
+async fn handle_local_static_route(
+    path: Path<String>,
+    state: State<AppState>,
+    uri: axum::http::Uri,
+    request_headers: HeaderMap,
+) -> Response {
+    let static_dir = state.config.proxy.static_dir.as_str().into();
+    handle_static_dir_route(path, state, uri, request_headers, static_dir).await
+}
+
+/// 处理static_dir或static_mounts中某个目录下的文件
+async fn handle_static_dir_route(
+    Path(path): Path<String>,
+    State(state): State<AppState>,
+    uri: axum::http::Uri,
+    request_headers: HeaderMap,
+    static_dir: std::sync::Arc<str>,
+) -> Response {
+    info!("[Black Hole] Received request: {}", uri.path());
+
+    if let Some(response) = chaos::inject(&state.config.chaos).await {
+        return response;
+    }
+
+    handle_local_static_request(&state, &request_headers, &static_dir, &path).await
+}
+
+async fn handle_local_static_request(
+    state: &AppState,
+    request_headers: &HeaderMap,
+    static_dir: &str,
+    file_path: &str,
+) -> Response {
+    if state.config.proxy.referer_check_static && !state.config.proxy.is_referer_allowed(request_headers) {
+        warn!("[Black Hole] Rejected request with disallowed referer: {:?}", request_headers.get(axum::http::header::REFERER));
+        return errors::error_response(request_headers, StatusCode::FORBIDDEN, "Forbidden: Referer not allowed");
+    }
+
+    // 安全路径验证
+    if !is_safe_path(file_path) {
+        warn!("[Black Hole] Detected unsafe path access: {}", file_path);
+        return errors::error_response(request_headers, StatusCode::FORBIDDEN, "Forbidden: Unsafe path");
+    }
+    
+    let local_path = PathBuf::from(static_dir).join(file_path);
+    
+    // 验证解析后的路径是否在允许的目录内
+    if !is_path_within_allowed_dirs(&local_path, static_dir) {
+        warn!("[Black Hole] Detected directory traversal attack: {:?}", local_path);
+        return errors::error_response(request_headers, StatusCode::FORBIDDEN, "Forbidden: Outside allowed directory range");
+    }
+    
+    info!("[Black Hole] Looking for local file: {:?}", local_path);
+
+    match async_fs::read(&local_path).await {
+        Ok(content) => {
+            let mut headers = HeaderMap::new();
+            set_content_type(&state.config.charset, &mut headers, file_path);
+            set_cache_control(&state.config.cache_control, &mut headers);
+            apply_path_headers(&state.config.proxy.path_headers, file_path, &mut headers);
+            let body = compression::encode_body(&state.config.compression, request_headers, &mut headers, content);
+            state.metrics.record_bytes_served(body.len());
+            
+            info!("[Black Hole] Successfully returned local file: {}", file_path);
+            // 本地静态文件不经过缓存
+            with_cache_status(state, (StatusCode::OK, headers, body).into_response(), CacheStatus::Bypass)
+        }
+        Err(_) => {
+            warn!("[Black Hole] File not found: {}", file_path);
+            errors::not_found_response(request_headers, &format!("File not found: {}", file_path))
+        }
+    }
+}
+
+/// 构建缓存文件路径，去掉版本号前的@符号以兼容Windows文件系统；
+/// 开启cache_case_insensitive时包名统一小写，使各平台行为一致
+fn cache_file_path(state: &AppState, package_name: &str, version: &str, file_path: &str) -> PathBuf {
+    let safe_version = version.trim_start_matches('@');
+    cache_package_dir(state, package_name)
+        .join(safe_version)
+        .join(file_path)
+}
+
+/// 包在缓存中的目录，其下每个子目录对应一个版本
+fn cache_package_dir(state: &AppState, package_name: &str) -> PathBuf {
+    let package_dir = if state.config.proxy.cache_case_insensitive {
+        package_name.to_ascii_lowercase()
+    } else {
+        package_name.to_string()
+    };
+    let shard_chars = state.config.proxy.cache_shard_chars;
+    if shard_chars == 0 {
+        return PathBuf::from(&state.config.proxy.cache_dir).join(package_dir);
+    }
+    // 按包名摘要的前N位十六进制字符分目录：cache_dir/ab/package/version/file
+    let shard = dedup::content_hash(package_dir.as_bytes());
+    PathBuf::from(&state.config.proxy.cache_dir)
+        .join(&shard[..shard_chars])
+        .join(package_dir)
+}
+
+/// 包的缓存版本数超过max_versions_per_package时，按修改时间淘汰最旧的版本（不淘汰当前版本）
+async fn evict_old_versions(state: &AppState, package_name: &str, current_version: &str) {
+    let limit = state.config.proxy.max_versions_per_package;
+    if limit == 0 {
+        return;
+    }
+
+    let package_dir = cache_package_dir(state, package_name);
+    let Ok(mut entries) = async_fs::read_dir(&package_dir).await else {
+        return;
+    };
+    let mut versions = Vec::new();
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let Ok(metadata) = entry.metadata().await else {
+            continue;
+        };
+        if metadata.is_dir() {
+            let modified = metadata.modified().unwrap_or(std::time::UNIX_EPOCH);
+            versions.push((modified, entry.path()));
+        }
+    }
+    if versions.len() <= limit {
+        return;
+    }
+
+    // 最新的排在前面，超出上限的部分被淘汰
+    versions.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+    let current_dir = package_dir.join(current_version.trim_start_matches('@'));
+    for (_, version_dir) in versions.into_iter().skip(limit) {
+        if version_dir == current_dir {
+            continue;
+        }
+        match async_fs::remove_dir_all(&version_dir).await {
+            Ok(()) => {
+                info!("[Black Hole] Evicted old cached version: {:?}", version_dir);
+                if let Some(meta_dir) = sidecar::meta_dir_path(&state.config.proxy.cache_dir, &version_dir) {
+                    let _ = async_fs::remove_dir_all(meta_dir).await;
+                }
+            }
+            Err(e) => warn!("[Black Hole] Failed to evict cached version {:?}: {}", version_dir, e),
+        }
+    }
+}
+
+/// 所有日志都在带有package、version、file字段的span中输出，便于按包过滤
+#[tracing::instrument(
+    name = "unpkg",
+    skip_all,
+    fields(package = %package_name, version = %version, file = %file_path)
+)]
+async fn handle_unpkg_request(
+    state: &AppState,
+    request_headers: &HeaderMap,
+    package_name: &str,
+    version: &str,
+    file_path: &str,
+    bypass_cache: bool,
+) -> Response {
+    if !state.config.proxy.is_referer_allowed(request_headers) {
+        warn!("[Black Hole] Rejected request with disallowed referer: {:?}", request_headers.get(axum::http::header::REFERER));
+        return errors::error_response(request_headers, StatusCode::FORBIDDEN, "Forbidden: Referer not allowed");
+    }
+
+    // 规范化文件路径，使逻辑上相同的请求共享同一个缓存条目
+    let Some(normalized_path) = normalize_file_path(file_path, state.config.proxy.normalize_paths) else {
+        warn!("[Black Hole] Detected unsafe path access: {}", file_path);
+        return errors::error_response(request_headers, StatusCode::FORBIDDEN, "Forbidden: Unsafe path");
+    };
+    let file_path = normalized_path.as_str();
+
+    // 包名和版本同样是缓存路径的一部分，必须逐段检查
+    if !is_safe_package_key(package_name, version) {
+        warn!("[Black Hole] Detected unsafe package or version: {}@{}", package_name, version);
+        return errors::error_response(request_headers, StatusCode::FORBIDDEN, "Forbidden: Unsafe path");
+    }
+
+    if !state.config.proxy.is_extension_allowed(file_path) {
+        warn!("[Black Hole] Rejected file with disallowed extension: {}", file_path);
+        return errors::error_response(request_headers, StatusCode::FORBIDDEN, "Forbidden: File extension not allowed");
+    }
+
+    // 配置了别名的版本（如 react@latest）直接改写为固定版本，不再经过registry解析
+    let version = match state.config.proxy.aliased_version(package_name, version) {
+        Some(pinned) => {
+            info!("[Black Hole] Pinned {}@{} to {}", package_name, version, pinned);
+            pinned
+        }
+        None => version,
+    };
+
+    // 通配符版本需要先解析为具体版本，缓存按具体版本存放
+    let resolved_version;
+    let version = if resolve::needs_resolution(version) {
+        if !state.config.proxy.enabled {
+            return with_cache_status(
+                state,
+                proxy_disabled_response(state, request_headers, package_name, version, file_path),
+                CacheStatus::Bypass,
+            );
+        }
+        resolved_version = match resolve::resolve_version(state, package_name, version).await {
+            Ok(resolved) => resolved,
+            Err(e) => return errors::error_response(request_headers, e.status, &e.message),
+        };
+        resolved_version.as_str()
+    } else {
+        version
+    };
+
+    // 响应使用的完整路径（package@version/file），用于按目录匹配charset
+    let served_path = format!("{}@{}/{}", package_name, version.trim_start_matches('@'), file_path);
+
+    // 本地镜像优先于缓存和上游，且从不写入
+    if let Some(mirror_dir) = &state.config.proxy.mirror_dir {
+        let mirror_file = PathBuf::from(mirror_dir)
+            .join(package_name)
+            .join(version.trim_start_matches('@'))
+            .join(file_path);
+        if is_path_within_allowed_dirs(&mirror_file, mirror_dir)
+            && let Ok(content) = async_fs::read(&mirror_file).await
+        {
+            info!("[Black Hole] Using mirror file: {:?}", mirror_file);
+            state.metrics.record_hit();
+            let response = proxied_file_response(state, request_headers, &served_path, content);
+            return with_cache_status(state, response, CacheStatus::Hit);
+        }
+    }
+
+    let cached_file = cache_file_path(state, package_name, version, file_path);
+    if !is_within_cache_dir(&state.config.proxy.cache_dir, &cached_file) {
+        warn!("[Black Hole] Detected directory traversal attack: {:?}", cached_file);
+        return errors::error_response(request_headers, StatusCode::FORBIDDEN, "Forbidden: Outside allowed directory range");
+    }
+
+    info!("[Black Hole] Checking cache file: {:?}", cached_file.display());
+
+    // 匹配no_cache_patterns的文件（如会变化的元数据）每次都从上游获取，不读也不写缓存
+    let never_cache = state.no_cache_patterns.iter().any(|pattern| pattern.is_match(&served_path));
+
+    // 会访问上游时，超过cache_ttl_secs的缓存文件重新下载；无法访问上游时仍返回已有的文件
+    let can_fetch = state.config.proxy.enabled
+        && !state.config.proxy.offline
+        && state.config.proxy.mode == ProxyMode::Proxy
+        && !peer::is_peer_request(request_headers);
+    let expired = can_fetch && !bypass_cache && !never_cache && is_cache_expired(state, &cached_file).await;
+
+    // 检查缓存是否存在，读取缓存不需要加锁；请求绕过缓存时直接重新下载并覆盖
+    if bypass_cache {
+        info!("[Black Hole] Bypassing cache for: {:?}", cached_file);
+    } else if expired {
+        info!("[Black Hole] Cache file expired, revalidating: {:?}", cached_file);
+    } else if never_cache {
+        info!("[Black Hole] Not caching (no_cache_patterns): {}", served_path);
+    } else if let Some(response) = cached_file_response(state, request_headers, &served_path, &cached_file).await {
+        return with_cache_status(state, response, CacheStatus::Hit);
+    }
+
+    if !state.config.proxy.enabled {
+        let response = proxy_disabled_response(state, request_headers, package_name, version, file_path);
+        return with_cache_status(state, response, CacheStatus::Bypass);
+    }
+
+    if state.config.proxy.offline {
+        warn!("[Black Hole] File not cached (offline mode): {}", file_path);
+        let response = errors::not_found_response(request_headers, &format!("File not cached: {}", file_path));
+        return with_cache_status(state, response, CacheStatus::Miss);
+    }
+
+    // 其他实例发来的请求只返回本地缓存，由请求方自己访问上游
+    if peer::is_peer_request(request_headers) {
+        info!("[Black Hole] File not cached for peer request: {}", file_path);
+        let response = errors::not_found_response(request_headers, &format!("File not cached: {}", file_path));
+        return with_cache_status(state, response, CacheStatus::Miss);
+    }
+
+    match state.config.proxy.mode {
+        ProxyMode::Proxy => {}
+        ProxyMode::Redirect => {
+            let url = state.resolver.url(package_name, version, file_path);
+            info!("[Black Hole] Redirecting to upstream: {}", url);
+            return with_cache_status(state, upstream_redirect(&url), CacheStatus::Miss);
+        }
+        ProxyMode::CacheOnly => {
+            warn!("[Black Hole] File not cached (cache_only mode): {}", file_path);
+            let response = errors::not_found_response(request_headers, &format!("File not cached: {}", file_path));
+            return with_cache_status(state, response, CacheStatus::Miss);
+        }
+    }
+
+    // 测试用：开启allow_upstream_override时，X-Upstream-Base请求头覆盖本次请求的上游地址
+    let upstream_base = if state.config.proxy.allow_upstream_override {
+        request_headers.get("x-upstream-base").and_then(|v| v.to_str().ok())
+    } else {
+        None
+    };
+    if let Some(base) = upstream_base
+        && !(base.starts_with("http://") || base.starts_with("https://"))
+    {
+        return errors::error_response(request_headers, StatusCode::BAD_REQUEST, "X-Upstream-Base must be an http(s) URL");
+    }
+
+    if never_cache {
+        state.metrics.record_miss();
+        // 开启micro_cache_ttl_ms时，同一文件的并发和短时间内的重复请求共用一次下载
+        let fetched = state
+            .micro_cache
+            .get_or_fetch(
+                &served_path,
+                Duration::from_millis(state.config.proxy.micro_cache_ttl_ms),
+                state.config.proxy.micro_cache_max_entries,
+                || fetch_uncached(state, package_name, version, file_path, upstream_base),
+            )
+            .await;
+        if let Err(e) = &fetched
+            && let Some(response) = local_fallback_response(state, request_headers, package_name, version, file_path, e).await
+        {
+            return response;
+        }
+        let response = match fetched {
+            Ok(content) => proxied_file_response(state, request_headers, &served_path, content.into()),
+            Err(e) => e.error_response(request_headers),
+        };
+        return with_cache_status(state, response, CacheStatus::Miss);
+    }
+
+    // 下载和写入期间持有该缓存路径的写锁；拿到锁后再检查一次，
+    // 等待期间其他请求可能已经写好了缓存
+    let guard = state.cache_locks.lock(&cached_file).await;
+    // 过期的文件可能已在等待期间被其他请求刷新
+    let refresh = bypass_cache || (expired && is_cache_expired(state, &cached_file).await);
+    if !refresh
+        && let Some(response) = cached_file_response(state, request_headers, &served_path, &cached_file).await
+    {
+        return with_cache_status(state, response, CacheStatus::Hit);
+    }
+
+    state.metrics.record_miss();
+
+    // 开启range_passthrough时，Range请求只从上游取该范围返回给客户端，完整文件继续在后台写入缓存；
+    // 范围请求要在后台下载开始前先占用上游名额，否则max_connections_per_host较小时会排在完整下载之后
+    let override_resolver = upstream_base.and_then(|base| state.resolver.with_base_url(base));
+    let resolver = override_resolver.as_deref().unwrap_or(state.resolver.as_ref());
+    let range_fetch = request_headers
+        .get(axum::http::header::RANGE)
+        .and_then(|v| v.to_str().ok())
+        .filter(|_| state.config.proxy.range_passthrough)
+        .and_then(|range| Some((range, resolver.fetch_range(package_name, version, file_path, range)?)));
+    let range_permit = match range_fetch {
+        Some(_) => state.host_limiter.acquire(&resolver.url(package_name, version, file_path)).await,
+        None => None,
+    };
+
+    // 下载和写入缓存在独立任务中完成并持有写锁，客户端读取缓慢或中途断开时
+    // 不会中断写入而留下不完整的缓存
+    let existing_file = cached_file.clone();
+    let download = tokio::spawn(tracing::Instrument::in_current_span({
+        let state = state.clone();
+        let package_name = package_name.to_string();
+        let version = version.to_string();
+        let file_path = file_path.to_string();
+        let upstream_base = upstream_base.map(str::to_string);
+        async move {
+            let _guard = guard;
+            download_to_cache(&state, &package_name, &version, &file_path, &cached_file, upstream_base.as_deref()).await
+        }
+    }));
+
+    if let Some((range, fetch)) = range_fetch {
+        let _permit = range_permit;
+        log_download(
+            state,
+            &format!("Downloading range {} from unpkg: {}", range, resolver.url(package_name, version, file_path)),
+        );
+        let response = match fetch.await {
+            Ok(upstream) => range_response(state, &served_path, upstream),
+            Err(e) => e.error_response(request_headers),
+        };
+        return with_cache_status(state, response, CacheStatus::Miss);
+    }
+
+    let result = download.await.unwrap_or_else(|e| {
+        error!("[Black Hole] Download task failed: {}", e);
+        Err(DownloadError {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            message: "Download task failed".to_string(),
+            location: None,
+            upstream_body: None,
+        })
+    });
+
+    if let Err(e) = &result
+        && let Some(response) = local_fallback_response(state, request_headers, package_name, version, file_path, e).await
+    {
+        return response;
+    }
+
+    let downloaded = if bypass_cache { CacheStatus::Bypass } else { CacheStatus::Miss };
+    match result {
+        Ok(content) => {
+            info!("[Black Hole] Successfully downloaded and cached file: {}", file_path);
+            let response = proxied_file_response(state, request_headers, &served_path, content.to_vec());
+            with_cache_status(state, response, downloaded)
+        }
+        // 开启serve_stale_on_error时，刷新已有缓存（过期或绕过缓存）失败则返回旧文件
+        Err(e) if state.config.proxy.serve_stale_on_error && (expired || bypass_cache) && e.location.is_none() => {
+            match cached_file_response(state, request_headers, &served_path, &existing_file).await {
+                Some(mut response) => {
+                    warn!("[Black Hole] Upstream failed ({}), serving stale cache: {:?}", e.message, existing_file);
+                    response.headers_mut().insert(
+                        axum::http::header::WARNING,
+                        axum::http::HeaderValue::from_static("110 - \"Response is Stale\""),
+                    );
+                    with_cache_status(state, response, CacheStatus::Stale)
+                }
+                None => with_cache_status(state, e.error_response(request_headers), downloaded),
+            }
+        }
+        Err(e) => with_cache_status(state, e.error_response(request_headers), downloaded),
+    }
+}
+
+/// 开启local_fallback且上游返回404时，改为返回static_dir下同一相对路径（package@version/file）的本地文件；
+/// 本地文件不存在时返回None，仍按上游的404处理
+async fn local_fallback_response(
+    state: &AppState,
+    request_headers: &HeaderMap,
+    package_name: &str,
+    version: &str,
+    file_path: &str,
+    error: &DownloadError,
+) -> Option<Response> {
+    if !state.config.proxy.local_fallback || error.status != StatusCode::NOT_FOUND || error.location.is_some() {
+        return None;
+    }
+    let relative = format!("{}@{}/{}", package_name, version, file_path);
+    let static_dir = &state.config.proxy.static_dir;
+    let local_path = PathBuf::from(static_dir).join(&relative);
+    if !is_safe_path(&relative)
+        || !is_path_within_allowed_dirs(&local_path, static_dir)
+        || !async_fs::metadata(&local_path).await.is_ok_and(|m| m.is_file())
+    {
+        return None;
+    }
+    info!("[Black Hole] Upstream returned 404, falling back to local file: {:?}", local_path);
+    Some(handle_local_static_request(state, request_headers, static_dir, &relative).await)
+}
+
+/// 响应的来源，通过server.cache_status_header（默认X-Cache）返回给客户端
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CacheStatus {
+    /// 来自磁盘缓存或本地镜像
+    Hit,
+    /// 从上游下载（或上游失败、文件未缓存）
+    Miss,
+    /// 未使用缓存：请求绕过缓存、代理未启用或本地静态文件
+    Bypass,
+    /// 上游失败时返回的过期缓存
+    Stale,
+}
+
+impl CacheStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            CacheStatus::Hit => "HIT",
+            CacheStatus::Miss => "MISS",
+            CacheStatus::Bypass => "BYPASS",
+            CacheStatus::Stale => "STALE",
+        }
+    }
+}
+
+/// 设置缓存状态响应头，server.cache_status_header为空时不设置
+fn with_cache_status(state: &AppState, mut response: Response, status: CacheStatus) -> Response {
+    let name = &state.config.server.cache_status_header;
+    if !name.is_empty()
+        && let Ok(name) = axum::http::HeaderName::from_bytes(name.as_bytes())
+    {
+        response
+            .headers_mut()
+            .insert(name, axum::http::HeaderValue::from_static(status.as_str()));
+    }
+    response
+}
+
+/// 缓存文件写入时间超过cache_ttl_secs时视为过期；优先使用元数据中的写入时间，
+/// 去重时硬链接指向的blob修改时间可能早于本次写入
+async fn is_cache_expired(state: &AppState, cached_file: &std::path::Path) -> bool {
+    let ttl = state.config.proxy.cache_ttl_secs;
+    if ttl == 0 {
+        return false;
+    }
+    let cached_at = match sidecar::read(&state.config.proxy.cache_dir, cached_file).await {
+        Some(metadata) => metadata.cached_at,
+        None => match async_fs::metadata(cached_file).await.and_then(|metadata| metadata.modified()) {
+            Ok(modified) => modified
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            Err(_) => return false,
+        },
+    };
+    sidecar::unix_now().saturating_sub(cached_at) > ttl
+}
+
+/// 将上游的部分内容响应返回给客户端，不压缩也不写入缓存
+fn range_response(state: &AppState, served_path: &str, upstream: upstream::UpstreamRangeResponse) -> Response {
+    let mut headers = HeaderMap::new();
+    set_content_type(&state.config.charset, &mut headers, served_path);
+    if let Some(content_range) = upstream
+        .content_range
+        .as_deref()
+        .and_then(|v| axum::http::HeaderValue::from_str(v).ok())
+    {
+        headers.insert(axum::http::header::CONTENT_RANGE, content_range);
+    }
+    headers.insert(axum::http::header::ACCEPT_RANGES, axum::http::HeaderValue::from_static("bytes"));
+    state.metrics.record_bytes_served(upstream.content.len());
+    let status = StatusCode::from_u16(upstream.status).unwrap_or(StatusCode::OK);
+    (status, headers, upstream.content).into_response()
+}
+
+/// 代理未启用且缓存未命中时的响应：按配置返回错误或重定向到上游
+fn proxy_disabled_response(
+    state: &AppState,
+    request_headers: &HeaderMap,
+    package_name: &str,
+    version: &str,
+    file_path: &str,
+) -> Response {
+    if state.config.proxy.proxy_disabled_redirect {
+        let url = state.resolver.url(package_name, version, file_path);
+        info!("[Black Hole] Proxy disabled, redirecting to upstream: {}", url);
+        return upstream_redirect(&url);
+    }
+    let status = StatusCode::from_u16(state.config.proxy.proxy_disabled_status)
+        .unwrap_or(StatusCode::SERVICE_UNAVAILABLE);
+    errors::error_response(request_headers, status, &state.config.proxy.proxy_disabled_message)
+}
+
+/// 302重定向到上游地址
+fn upstream_redirect(url: &str) -> Response {
+    match axum::http::HeaderValue::from_str(url) {
+        Ok(location) => (StatusCode::FOUND, [(axum::http::header::LOCATION, location)]).into_response(),
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Invalid upstream URL").into_response(),
+    }
+}
+
+/// 缓存命中时构建响应，缓存文件不存在时返回None
+async fn cached_file_response(
+    state: &AppState,
+    request_headers: &HeaderMap,
+    file_path: &str,
+    cached_file: &std::path::Path,
+) -> Option<Response> {
+    let metadata = cache_entry_metadata(state, file_path, cached_file).await?;
+    let compressed = metadata.compressed;
+
+    // 由前端nginx通过内部location直接发送文件；gzip存储的文件需要设置Content-Encoding，仍由本服务返回。
+    // 其他实例直接访问本服务而不经过nginx，需要返回文件内容
+    if state.config.proxy.x_accel_redirect && !compressed && !peer::is_peer_request(request_headers) {
+        let length = async_fs::metadata(cached_file).await.ok().filter(|m| m.is_file())?.len();
+        let response = x_accel_response(state, file_path, cached_file)?;
+        info!("[Black Hole] Using cached file (X-Accel-Redirect): {:?}", cached_file);
+        state.metrics.record_hit();
+        record_access(state, cached_file, &metadata);
+        state.metrics.record_bytes_served(length as usize);
+        return Some(response);
+    }
+
+    // 快速路径：磁盘上已是gzip且客户端接受gzip时，直接流式返回文件，不读入内存也不解压
+    if compressed && compression::accepts(request_headers, compression::Encoding::Gzip) {
+        let mut file = async_fs::File::open(cached_file).await.ok()?;
+        if !has_gzip_magic(&mut file).await {
+            warn!("[Black Hole] Cached file is not gzip despite metadata, ignoring: {:?}", cached_file);
+            return None;
+        }
+        let length = file.metadata().await.ok()?.len();
+        info!("[Black Hole] Using cached file (gzip passthrough): {:?}", cached_file);
+        state.metrics.record_hit();
+        record_access(state, cached_file, &metadata);
+        return Some(gzip_passthrough_response(state, file_path, file, length));
+    }
+
+    let content = async_fs::read(cached_file).await.ok()?;
+    if compressed && !content.starts_with(&GZIP_MAGIC) {
+        warn!("[Black Hole] Cached file is not gzip despite metadata, ignoring: {:?}", cached_file);
+        return None;
+    }
+    info!("[Black Hole] Using cached file: {:?}", cached_file);
+    state.metrics.record_hit();
+    record_access(state, cached_file, &metadata);
+    if compressed {
+        return Some(decompressed_file_response(state, request_headers, file_path, content));
+    }
+    Some(proxied_file_response(state, request_headers, file_path, content))
+}
+
+/// 缓存文件的元数据，返回None表示按未命中处理；缓存命中的判断（返回文件、/cache/info、预取）都以此为准。
+/// 没有元数据的文件（引入元数据之前写入的、或元数据写入失败时留下的旧文件）按未压缩的原文处理，
+/// 并在后台补写元数据；这类文件以gzip魔数开头（本身又不是.gz文件）时无法判断存储格式，仍按未命中处理
+async fn cache_entry_metadata(state: &AppState, file_path: &str, cached_file: &std::path::Path) -> Option<sidecar::CacheMetadata> {
+    let cache_dir = &state.config.proxy.cache_dir;
+    if let Some(metadata) = sidecar::read(cache_dir, cached_file).await {
+        return Some(metadata);
+    }
+    let file_metadata = async_fs::metadata(cached_file).await.ok().filter(|m| m.is_file())?;
+    let content = async_fs::read(cached_file).await.ok()?;
+    if content.starts_with(&GZIP_MAGIC) && !is_gzip_file(file_path) {
+        warn!("[Black Hole] Ignoring gzip cached file without metadata: {:?}", cached_file);
+        return None;
+    }
+
+    let mut metadata = sidecar::CacheMetadata::new(content_type_for(file_path), &content);
+    // 写入时间取文件的修改时间，补写元数据不会让旧文件重新计算cache_ttl_secs
+    if let Some(modified) = file_metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.duration_since(std::time::UNIX_EPOCH).ok())
+    {
+        metadata.cached_at = modified.as_secs();
+    }
+
+    // 调用方可能正持有该路径的写锁，因此在后台补写；拿到锁后确认期间没有下载写入新的元数据或内容
+    let state = state.clone();
+    let cached_file = cached_file.to_path_buf();
+    let backfill = metadata.clone();
+    tokio::spawn(async move {
+        let _guard = state.cache_locks.lock(&cached_file).await;
+        let cache_dir = &state.config.proxy.cache_dir;
+        if sidecar::read(cache_dir, &cached_file).await.is_some() {
+            return;
+        }
+        if !async_fs::read(&cached_file).await.is_ok_and(|current| backfill.matches(&current)) {
+            return;
+        }
+        if sidecar::write(cache_dir, &cached_file, &backfill).await.is_ok() {
+            info!("[Black Hole] Backfilled metadata for cached file: {:?}", cached_file);
+        }
+    });
+    Some(metadata)
+}
+
+/// 本身就是gzip格式的文件（如 .gz、.tgz），内容以gzip魔数开头是正常的
+fn is_gzip_file(file_path: &str) -> bool {
+    std::path::Path::new(file_path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("gz") || ext.eq_ignore_ascii_case("tgz"))
+}
+
+/// gzip数据的前两个字节
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// 检查文件是否以gzip魔数开头，检查后将读取位置移回文件开头
+async fn has_gzip_magic(file: &mut async_fs::File) -> bool {
+    use tokio::io::{AsyncReadExt, AsyncSeekExt};
+    let mut magic = [0u8; 2];
+    file.read_exact(&mut magic).await.is_ok()
+        && magic == GZIP_MAGIC
+        && file.seek(std::io::SeekFrom::Start(0)).await.is_ok()
+}
+
+/// 只返回响应头，X-Accel-Redirect指向缓存文件在x_accel_prefix下的内部地址，由nginx发送文件内容
+fn x_accel_response(state: &AppState, file_path: &str, cached_file: &std::path::Path) -> Option<Response> {
+    let relative = cached_file.strip_prefix(&state.config.proxy.cache_dir).ok()?;
+    let relative = relative
+        .components()
+        .map(|c| percent_encode_segment(&c.as_os_str().to_string_lossy()))
+        .collect::<Vec<_>>()
+        .join("/");
+    let location = format!("{}/{}", state.config.proxy.x_accel_prefix.trim_end_matches('/'), relative);
+    let mut headers = HeaderMap::new();
+    set_content_type(&state.config.charset, &mut headers, file_path);
+    set_cache_control(&state.config.cache_control, &mut headers);
+    headers.insert("x-accel-redirect", axum::http::HeaderValue::from_str(&location).ok()?);
+    Some((StatusCode::OK, headers).into_response())
+}
+
+/// 对路径段做百分号编码，保留RFC 3986的非保留字符和 `@`
+fn percent_encode_segment(segment: &str) -> String {
+    let mut encoded = String::with_capacity(segment.len());
+    for byte in segment.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'@' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// 记录缓存命中的访问时间，proxy.access_flush_interval_secs为0时不记录
+fn record_access(state: &AppState, cached_file: &std::path::Path, metadata: &sidecar::CacheMetadata) {
+    if state.config.proxy.access_flush_interval_secs > 0 {
+        state.access.record(cached_file, metadata);
+    }
+}
+
+/// 构建代理文件的响应：设置Content-Type、按需压缩并计数
+fn proxied_file_response(
+    state: &AppState,
+    request_headers: &HeaderMap,
+    file_path: &str,
+    content: Vec<u8>,
+) -> Response {
+    let mut headers = HeaderMap::new();
+    set_content_type(&state.config.charset, &mut headers, file_path);
+    set_cache_control(&state.config.cache_control, &mut headers);
+    let body = compression::encode_body(&state.config.compression, request_headers, &mut headers, content);
+    state.metrics.record_bytes_served(body.len());
+    (StatusCode::OK, headers, body).into_response()
+}
+
+/// 将磁盘上以gzip存储的缓存文件原样流式返回，并设置Content-Encoding: gzip
+fn gzip_passthrough_response(state: &AppState, file_path: &str, file: async_fs::File, length: u64) -> Response {
+    let mut headers = HeaderMap::new();
+    set_content_type(&state.config.charset, &mut headers, file_path);
+    set_cache_control(&state.config.cache_control, &mut headers);
+    headers.insert(
+        axum::http::header::CONTENT_ENCODING,
+        axum::http::HeaderValue::from_static("gzip"),
+    );
+    compression::set_vary_accept_encoding(&mut headers);
+    headers.insert(axum::http::header::CONTENT_LENGTH, length.into());
+    state.metrics.record_bytes_served(length as usize);
+    let stream = tokio_util::io::ReaderStream::with_capacity(file, state.config.server.stream_chunk_bytes);
+    let body = axum::body::Body::from_stream(stream);
+    (StatusCode::OK, headers, body).into_response()
+}
+
+/// 客户端不接受gzip时，解压磁盘上以gzip存储的缓存文件后返回
+fn decompressed_file_response(
+    state: &AppState,
+    request_headers: &HeaderMap,
+    file_path: &str,
+    content: Vec<u8>,
+) -> Response {
+    match compression::gunzip(&content) {
+        Ok(decompressed) => {
+            // 同一缓存文件对接受gzip的客户端原样返回，响应同样随Accept-Encoding变化
+            let mut response = proxied_file_response(state, request_headers, file_path, decompressed);
+            compression::set_vary_accept_encoding(response.headers_mut());
+            response
+        }
+        Err(e) => {
+            error!("[Black Hole] Failed to decompress cached file {}: {}", file_path, e);
+            errors::error_response(request_headers, StatusCode::INTERNAL_SERVER_ERROR, "Failed to decompress cached file")
+        }
+    }
+}
+
+/// 上游下载失败时返回给客户端的状态码和消息
+#[derive(Debug)]
+struct DownloadError {
+    status: StatusCode,
+    message: String,
+    /// 上游返回重定向且配置为透传时的目标地址
+    location: Option<String>,
+    /// 开启transparent_upstream_errors时原样返回的上游错误响应体
+    upstream_body: Option<upstream::UpstreamErrorBody>,
+}
+
+impl DownloadError {
+    /// 转换为返回给客户端的响应，透传的重定向带上Location，透传的上游错误原样返回
+    fn error_response(self, request_headers: &HeaderMap) -> Response {
+        if let Some(upstream_body) = self.upstream_body {
+            let mut headers = HeaderMap::new();
+            if let Some(content_type) = upstream_body
+                .content_type
+                .as_deref()
+                .and_then(|v| axum::http::HeaderValue::from_str(v).ok())
+            {
+                headers.insert(axum::http::header::CONTENT_TYPE, content_type);
+            }
+            return (self.status, headers, upstream_body.body).into_response();
+        }
+        match self.location.as_deref().map(axum::http::HeaderValue::from_str) {
+            Some(Ok(location)) => (self.status, [(axum::http::header::LOCATION, location)]).into_response(),
+            _ if self.status == StatusCode::NOT_FOUND => errors::not_found_response(request_headers, &self.message),
+            _ => errors::error_response(request_headers, self.status, &self.message),
+        }
+    }
+}
+
+/// 记录上游下载地址；开启log.quiet_downloads时降为debug级别，失败时上游模块仍以error级别记录地址
+fn log_download(state: &AppState, message: &str) {
+    if state.config.log.quiet_downloads {
+        debug!("[Black Hole] {}", message);
+    } else {
+        info!("[Black Hole] {}", message);
+    }
+}
+
+/// 从上游下载文件并计入上游指标，开启strict_content_type时检查上游的Content-Type
+async fn fetch_upstream(
+    state: &AppState,
+    resolver: &dyn upstream::UpstreamResolver,
+    package_name: &str,
+    version: &str,
+    file_path: &str,
+) -> Result<upstream::UpstreamResponse, DownloadError> {
+    let url = resolver.url(package_name, version, file_path);
+    let _permit = state.host_limiter.acquire(&url).await;
+    log_download(state, &format!("Downloading from unpkg: {}", url));
+    let started = std::time::Instant::now();
+    let response = resolver.fetch(package_name, version, file_path).await?;
+
+    // 上游返回的类型与扩展名不符（如 .js 返回了HTML）时不缓存也不返回，避免同源下的内容类型混淆
+    let expected_content_type = content_type_for(file_path);
+    if state.config.proxy.strict_content_type
+        && let Some(upstream_content_type) = response.content_type.as_deref()
+        && !content_type_matches(expected_content_type, upstream_content_type)
+    {
+        error!(
+            "[Black Hole] Upstream content type mismatch for {}: expected {}, got {}",
+            file_path, expected_content_type, upstream_content_type
+        );
+        return Err(DownloadError {
+            status: StatusCode::BAD_GATEWAY,
+            message: "Upstream returned an unexpected content type".to_string(),
+            location: None,
+            upstream_body: None,
+        });
+    }
+
+    state.metrics.upstream_latency.observe(started.elapsed());
+    state.metrics.record_bytes_downloaded(response.content.len());
+    Ok(response)
+}
+
+/// 从上游下载但不写入缓存，用于匹配no_cache_patterns的文件
+async fn fetch_uncached(
+    state: &AppState,
+    package_name: &str,
+    version: &str,
+    file_path: &str,
+    upstream_base: Option<&str>,
+) -> Result<axum::body::Bytes, DownloadError> {
+    let override_resolver = upstream_base.and_then(|base| state.resolver.with_base_url(base));
+    let resolver = override_resolver.as_deref().unwrap_or(state.resolver.as_ref());
+    let response = fetch_upstream(state, resolver, package_name, version, file_path).await?;
+    Ok(response.content)
+}
+
+/// 通过上游解析器下载文件并写入缓存
+async fn download_to_cache(
+    state: &AppState,
+    package_name: &str,
+    version: &str,
+    file_path: &str,
+    cached_file: &std::path::Path,
+    upstream_base: Option<&str>,
+) -> Result<axum::body::Bytes, DownloadError> {
+    // 离线模式下保证不发起任何上游请求
+    if state.config.proxy.offline {
+        return Err(DownloadError {
+            status: StatusCode::SERVICE_UNAVAILABLE,
+            message: "Offline mode: upstream access is disabled".to_string(),
+            location: None,
+            upstream_body: None,
+        });
+    }
+    // 写入缓存前再次确认目标位于cache_dir内
+    if !is_within_cache_dir(&state.config.proxy.cache_dir, cached_file) {
+        warn!("[Black Hole] Refusing to write outside cache_dir: {:?}", cached_file);
+        return Err(DownloadError {
+            status: StatusCode::FORBIDDEN,
+            message: "Forbidden: Outside allowed directory range".to_string(),
+            location: None,
+            upstream_body: None,
+        });
+    }
+
+    let override_resolver = upstream_base.and_then(|base| {
+        info!("[Black Hole] Using upstream override for this request: {}", base);
+        state.resolver.with_base_url(base)
+    });
+    let resolver = override_resolver.as_deref().unwrap_or(state.resolver.as_ref());
+
+    // 先尝试从其他实例的缓存获取，都未命中时再访问上游；覆盖了上游地址的请求不经过其他实例
+    let peer_content = if upstream_base.is_none() {
+        peer::fetch(state, package_name, version, file_path).await
+    } else {
+        None
+    };
+    let (content, final_url) = match peer_content {
+        Some(content) => (content, None),
+        None => {
+            let response = fetch_upstream(state, resolver, package_name, version, file_path).await?;
+            (response.content, Some(response.final_url))
+        }
+    };
+
+    let mut metadata = sidecar::CacheMetadata::new(content_type_for(file_path), &content);
+
+    // 启用compress_cache时以gzip压缩存储，压缩失败则存储原始内容
+    let mut stored = std::borrow::Cow::Borrowed(content.as_ref());
+    if state.config.proxy.compress_cache {
+        match compression::compress(&content, compression::Encoding::Gzip) {
+            Ok(compressed) => {
+                stored = std::borrow::Cow::Owned(compressed);
+                metadata.compressed = true;
+            }
+            Err(e) => warn!("[Black Hole] Failed to compress cache file: {}", e),
+        }
+    }
+
+    write_cache_file(state, package_name, version, cached_file, &stored, &metadata).await;
+
+    // 上游经过重定向（如 pkg@latest/file -> pkg@1.2.3/file）时，同时缓存到最终地址对应的路径，
+    // 之后直接请求具体版本时可以命中缓存
+    // 最终地址同样需要满足请求路径的格式和安全检查
+    if let Some((final_package, final_version, final_file)) =
+        final_url.and_then(|final_url| resolver.parse_url(&final_url))
+        && state
+            .unpkg_regex
+            .is_match(&format!("{}@{}/{}", final_package, final_version, final_file))
+        && is_safe_package_key(&final_package, &final_version)
+        && let Some(final_file) = normalize_file_path(&final_file, state.config.proxy.normalize_paths)
+    {
+        let final_cached_file = cache_file_path(state, &final_package, &final_version, &final_file);
+        if final_cached_file != cached_file && is_within_cache_dir(&state.config.proxy.cache_dir, &final_cached_file) {
+            info!("[Black Hole] Also caching redirect target: {:?}", final_cached_file);
+            write_cache_file(state, &final_package, &final_version, &final_cached_file, &stored, &metadata).await;
+        }
+    }
+
+    Ok(content)
+}
+
+/// 写入缓存文件及其元数据，并按需淘汰旧版本
+async fn write_cache_file(
+    state: &AppState,
+    package_name: &str,
+    version: &str,
+    cached_file: &std::path::Path,
+    stored: &[u8],
+    metadata: &sidecar::CacheMetadata,
+) {
+    // 创建缓存目录（包括文件的父目录）
+    if let Some(parent_dir) = cached_file.parent() {
+        match async_fs::create_dir_all(parent_dir).await {
+            Ok(()) => permissions::apply_dir_mode(&state.config.proxy, parent_dir).await,
+            Err(e) => warn!("[Black Hole] Failed to create cache directory: {}", e),
+        }
+    }
+
+    // 读取缓存不加锁，元数据（记录是否以gzip存储）必须先于文件内容就位：文件已替换而元数据缺失或过时时，
+    // 读取者会把gzip内容当作原文返回。存储格式改变时先删除旧文件，避免新元数据与旧内容搭配
+    let cache_dir = &state.config.proxy.cache_dir;
+    if let Some(previous) = sidecar::read(cache_dir, cached_file).await
+        && previous.compressed != metadata.compressed
+    {
+        let _ = async_fs::remove_file(cached_file).await;
+    }
+    if let Err(e) = sidecar::write(cache_dir, cached_file, metadata).await {
+        warn!("[Black Hole] Not caching {:?}: failed to save metadata: {}", cached_file, e);
+        return;
+    }
+
+    // 保存到缓存，启用去重时相同内容只存储一份
+    let saved = if state.config.proxy.dedup {
+        dedup::store(cache_dir, cached_file, stored).await
+    } else {
+        write_atomic(cached_file, stored).await
+    };
+    match saved {
+        Ok(()) => {
+            permissions::apply_file_mode(&state.config.proxy, cached_file).await;
+            evict_old_versions(state, package_name, version).await;
+        }
+        Err(e) => warn!("[Black Hole] Failed to save cache file: {}", e),
+    }
+}
+
+/// 先写入同目录下的临时文件再重命名，读取者不会看到写了一半的文件，
+/// 写入失败时删除临时文件
+pub(crate) async fn write_atomic(path: &std::path::Path, content: &[u8]) -> std::io::Result<()> {
+    // 同一blob可能被不同缓存路径同时写入，临时文件名需要唯一
+    static NEXT_TEMP_ID: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+    let id = NEXT_TEMP_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    let mut temp = path.as_os_str().to_os_string();
+    temp.push(format!(".{}-{}{}", std::process::id(), id, cache_walk::TEMP_SUFFIX));
+    let temp = PathBuf::from(temp);
+    let result = match async_fs::write(&temp, content).await {
+        Ok(()) => async_fs::rename(&temp, path).await,
+        Err(e) => Err(e),
+    };
+    if result.is_err() {
+        let _ = async_fs::remove_file(&temp).await;
+    }
+    result
+}
+
+/// 按路径前缀附加配置的响应头，多条规则匹配时按顺序应用
+fn apply_path_headers(rules: &[PathHeaderRule], file_path: &str, headers: &mut HeaderMap) {
+    for rule in rules.iter().filter(|rule| rule.matches(file_path)) {
+        for (name, value) in &rule.headers {
+            if let (Ok(name), Ok(value)) = (
+                axum::http::HeaderName::from_bytes(name.as_bytes()),
+                axum::http::HeaderValue::from_str(value),
+            ) {
+                headers.insert(name, value);
+            }
+        }
+    }
+}
+
+/// 按扩展名设置Content-Type，并按charset配置附加字符编码
+fn set_content_type(config: &CharsetConfig, headers: &mut HeaderMap, file_path: &str) {
+    let content_type = content_type_for(file_path);
+    let value = match config.charset_for(content_type, file_path) {
+        Some(charset) => format!("{}; charset={}", content_type, charset),
+        None => content_type.to_string(),
+    };
+    let value = axum::http::HeaderValue::from_str(&value)
+        .unwrap_or_else(|_| axum::http::HeaderValue::from_static(content_type));
+    headers.insert(axum::http::header::CONTENT_TYPE, value);
+}
+
+/// 按Content-Type附加Cache-Control，max-age为0时使用no-cache；需在set_content_type之后调用
+fn set_cache_control(config: &CacheControlConfig, headers: &mut HeaderMap) {
+    if !config.enabled {
+        return;
+    }
+    let Some(content_type) = headers
+        .get(axum::http::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+    else {
+        return;
+    };
+    let value = match config.max_age_for(content_type) {
+        Some(0) => "no-cache".to_string(),
+        Some(secs) => format!("public, max-age={}", secs),
+        None => return,
+    };
+    if let Ok(value) = axum::http::HeaderValue::from_str(&value) {
+        headers.insert(axum::http::header::CACHE_CONTROL, value);
+    }
+}
+
+/// 根据文件扩展名推断Content-Type
+fn content_type_for(file_path: &str) -> &'static str {
+    let path_buf = PathBuf::from(file_path);
+    let ext = path_buf
+        .extension()
+        .and_then(|s| s.to_str())
+        .unwrap_or("");
+
+    match ext {
+        "css" => "text/css",
+        "js" => "application/javascript",
+        "json" => "application/json",
+        "html" => "text/html",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "ico" => "image/x-icon",
+        // WebAssembly.instantiateStreaming要求application/wasm，以inline方式返回
+        "wasm" => "application/wasm",
+        "woff2" => "font/woff2",
+        "woff" => "font/woff",
+        "ttf" => "font/ttf",
+        "otf" => "font/otf",
+        "eot" => "application/vnd.ms-fontobject",
+        _ => "application/octet-stream",
+    }
+}
+
+/// 上游的Content-Type是否与按扩展名推断的类型一致，忽略参数和大小写；
+/// 扩展名未知（application/octet-stream）时只拒绝HTML
+fn content_type_matches(expected: &str, upstream: &str) -> bool {
+    let essence = upstream.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+    let canonical = |content_type: &str| match content_type {
+        "text/javascript" | "application/x-javascript" | "application/ecmascript" | "text/ecmascript" => {
+            "application/javascript".to_string()
+        }
+        "image/vnd.microsoft.icon" => "image/x-icon".to_string(),
+        "application/font-woff" => "font/woff".to_string(),
+        "application/font-woff2" => "font/woff2".to_string(),
+        other => other.to_string(),
+    };
+    if expected == "application/octet-stream" {
+        return !matches!(essence.as_str(), "text/html" | "application/xhtml+xml");
+    }
+    canonical(&essence) == canonical(expected)
+}
+
+/// 规范化包内文件路径：去掉 `.` 段和多余的斜杠，出现 `..` 时返回None；
+/// 关闭规范化时只做安全检查，空段（开头或重复的 `/`）和 `.` 段同样返回None
+fn normalize_file_path(file_path: &str, normalize: bool) -> Option<String> {
+    let segments: Vec<&str> = file_path.split('/').collect();
+    if segments.iter().any(|segment| *segment == ".." || segment.contains('\\')) {
+        return None;
+    }
+    if !normalize {
+        if segments.iter().any(|segment| segment.is_empty() || *segment == ".") {
+            return None;
+        }
+        return Some(file_path.to_string());
+    }
+
+    let normalized = segments
+        .into_iter()
+        .filter(|segment| !segment.is_empty() && *segment != ".")
+        .collect::<Vec<_>>()
+        .join("/");
+    if normalized.is_empty() {
+        return None;
+    }
+    Some(normalized)
+}
+
+/// 检查路径是否安全，防止目录遍历攻击
+fn is_safe_path(path: &str) -> bool {
+    // 检查是否包含危险字符
+    if path.contains("..") || path.contains("//") || path.contains('\\') {
+        return false;
+    }
+    
+    // 允许以@开头的scoped npm包名（如@highlightjs/cdn-assets）
+    // 但不允许其他以/开头的绝对路径
+    if path.starts_with('/') && !path.starts_with("@") {
+        return false;
+    }
+    
+    // 检查是否为绝对路径
+    if PathBuf::from(path).is_absolute() {
+        return false;
+    }
+    
+    // 检查路径组件
+    for component in PathBuf::from(path).components() {
+        match component {
+            std::path::Component::ParentDir => return false,
+            std::path::Component::RootDir => return false,
+            std::path::Component::Prefix(_) => return false,
+            _ => {}
+        }
+    }
+    
+    true
+}
+
+/// 检查包名和版本能否安全地作为缓存路径的一部分：每一段都不能为空、`.` 或 `..`，
+/// 包名最多两段（scoped包名 `@scope/name`），版本只能有一段
+fn is_safe_package_key(package_name: &str, version: &str) -> bool {
+    let is_safe_segment = |segment: &str| !segment.is_empty() && segment != "." && segment != ".." && !segment.contains('\\');
+    let package_segments: Vec<&str> = package_name.split('/').collect();
+    let version = version.trim_start_matches('@');
+    is_safe_path(package_name)
+        && is_safe_path(version)
+        && package_segments.len() <= 2
+        && package_segments.iter().all(|segment| is_safe_segment(segment))
+        && !version.contains('/')
+        && is_safe_segment(version)
+}
+
+/// 缓存文件路径是否位于cache_dir内，读写缓存前调用
+fn is_within_cache_dir(cache_dir: &str, path: &std::path::Path) -> bool {
+    is_new_path_within(path, std::path::Path::new(cache_dir), |path| fs::canonicalize(path).ok())
+}
+
+/// 与 `is_path_within` 类似，但目标及其上级目录可以尚不存在（如未缓存文件的版本目录）：
+/// 向上找到最近的已存在路径并解析符号链接后比较，尚不存在的部分只能是普通路径段
+fn is_new_path_within(
+    target_path: &std::path::Path,
+    allowed_dir: &std::path::Path,
+    canonicalize: impl Fn(&std::path::Path) -> Option<PathBuf>,
+) -> bool {
+    let Some(allowed_path) = canonicalize(allowed_dir) else {
+        return false;
+    };
+    let mut existing = target_path;
+    loop {
+        if let Some(resolved) = canonicalize(existing) {
+            return resolved.starts_with(&allowed_path);
+        }
+        // 以 `..` 结尾或已到根部时无法继续向上
+        if existing.file_name().is_none() {
+            return false;
+        }
+        match existing.parent() {
+            Some(parent) => existing = parent,
+            None => return false,
+        }
+    }
+}
+
+/// 验证路径是否在允许的目录范围内
+fn is_path_within_allowed_dirs(target_path: &std::path::Path, allowed_dir: &str) -> bool {
+    is_path_within(target_path, std::path::Path::new(allowed_dir), |path| fs::canonicalize(path).ok())
+}
+
+/// 路径包含判断的纯逻辑部分，文件系统访问由 `canonicalize` 提供：
+/// 目标存在时比较其解析后的路径（符号链接会被解析，指向目录外即拒绝）；
+/// 目标尚不存在时改为检查其父目录，此时最后一段必须是普通文件名
+fn is_path_within(
+    target_path: &std::path::Path,
+    allowed_dir: &std::path::Path,
+    canonicalize: impl Fn(&std::path::Path) -> Option<PathBuf>,
+) -> bool {
+    let Some(allowed_path) = canonicalize(allowed_dir) else {
+        return false;
+    };
+
+    let target_canonical = match canonicalize(target_path) {
+        Some(path) => path,
+        None => {
+            // 如果文件不存在，检查其父目录
+            if target_path.file_name().is_none() {
+                return false;
+            }
+            let parent = target_path.parent().unwrap_or(target_path);
+            match canonicalize(parent) {
+                Some(path) => path,
+                None => return false,
+            }
+        }
+    };
+
+    target_canonical.starts_with(&allowed_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::path::Path;
+
+    /// 用固定的映射模拟文件系统：存在的路径映射到其解析后的真实路径
+    fn fake_fs(entries: &[(&str, &str)]) -> impl Fn(&Path) -> Option<PathBuf> {
+        let map: HashMap<PathBuf, PathBuf> = entries
+            .iter()
+            .map(|(from, to)| (PathBuf::from(from), PathBuf::from(to)))
+            .collect();
+        move |path| map.get(path).cloned()
+    }
+
+    #[test]
+    fn existing_file_inside_allowed_dir() {
+        let fs = fake_fs(&[("static", "/srv/static"), ("static/app.js", "/srv/static/app.js")]);
+        assert!(is_path_within(Path::new("static/app.js"), Path::new("static"), fs));
+    }
+
+    #[test]
+    fn traversal_resolving_outside_is_rejected() {
+        let fs = fake_fs(&[("static", "/srv/static"), ("static/../config.toml", "/srv/config.toml")]);
+        assert!(!is_path_within(Path::new("static/../config.toml"), Path::new("static"), fs));
+    }
+
+    #[test]
+    fn sibling_dir_with_common_prefix_is_rejected() {
+        let fs = fake_fs(&[("static", "/srv/static"), ("static/../static2/a.js", "/srv/static2/a.js")]);
+        assert!(!is_path_within(Path::new("static/../static2/a.js"), Path::new("static"), fs));
+    }
+
+    #[test]
+    fn symlink_escaping_allowed_dir_is_rejected() {
+        let fs = fake_fs(&[("static", "/srv/static"), ("static/link", "/etc/passwd")]);
+        assert!(!is_path_within(Path::new("static/link"), Path::new("static"), fs));
+    }
+
+    #[test]
+    fn symlink_within_allowed_dir_is_accepted() {
+        let fs = fake_fs(&[("static", "/srv/static"), ("static/latest.js", "/srv/static/v2/app.js")]);
+        assert!(is_path_within(Path::new("static/latest.js"), Path::new("static"), fs));
+    }
+
+    #[test]
+    fn missing_file_checks_parent_dir() {
+        let fs = fake_fs(&[("static", "/srv/static"), ("static/js", "/srv/static/js")]);
+        assert!(is_path_within(Path::new("static/js/missing.js"), Path::new("static"), fs));
+    }
+
+    #[test]
+    fn missing_file_under_escaping_parent_is_rejected() {
+        let fs = fake_fs(&[("static", "/srv/static"), ("static/link", "/etc")]);
+        assert!(!is_path_within(Path::new("static/link/missing"), Path::new("static"), fs));
+    }
+
+    #[test]
+    fn missing_file_with_missing_parent_is_rejected() {
+        let fs = fake_fs(&[("static", "/srv/static")]);
+        assert!(!is_path_within(Path::new("static/a/b.js"), Path::new("static"), fs));
+    }
+
+    #[test]
+    fn unresolvable_trailing_parent_component_is_rejected() {
+        let fs = fake_fs(&[("static", "/srv/static"), ("static/app.js", "/srv/static/app.js")]);
+        assert!(!is_path_within(Path::new("static/app.js/.."), Path::new("static"), fs));
+    }
+
+    #[test]
+    fn missing_allowed_dir_rejects_everything() {
+        let fs = fake_fs(&[("static/app.js", "/srv/static/app.js")]);
+        assert!(!is_path_within(Path::new("static/app.js"), Path::new("static"), fs));
+    }
+
+    #[test]
+    fn nested_dirs_overlap() {
+        assert!(dirs_overlap(Path::new("/srv/static"), Path::new("/srv/static/cache")));
+        assert!(dirs_overlap(Path::new("/srv/cache/static"), Path::new("/srv/cache")));
+        assert!(dirs_overlap(Path::new("./static"), Path::new("static/./cache")));
+    }
+
+    #[test]
+    fn identical_dirs_overlap() {
+        assert!(dirs_overlap(Path::new("/srv/static"), Path::new("/srv/static")));
+        assert!(dirs_overlap(Path::new("./static"), Path::new("static/")));
+        assert!(dirs_overlap(Path::new("/srv/cache/../static"), Path::new("/srv/static")));
+    }
+
+    #[test]
+    fn sibling_dirs_do_not_overlap() {
+        assert!(!dirs_overlap(Path::new("/srv/static"), Path::new("/srv/cache")));
+        assert!(!dirs_overlap(Path::new("./static"), Path::new("./cache")));
+        assert!(!dirs_overlap(Path::new("/srv/static"), Path::new("/srv/static2")));
+    }
+
+    #[test]
+    fn validate_reports_overlapping_dirs() {
+        let mut config: Config = toml::from_str(
+            r#"
+            [proxy]
+            enabled = true
+            static_dir = "./static"
+            cache_dir = "./static/cache"
+
+            [log]
+            enabled = false
+            level = "info"
+
+            [server]
+            port = 8080
+            host = "localhost"
+            "#,
+        )
+        .unwrap();
+        assert!(config.validate().iter().any(|problem| problem.contains("overlap")));
+
+        config.proxy.cache_dir = "./cache".to_string();
+        assert!(!config.validate().iter().any(|problem| problem.contains("overlap")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn real_symlink_escape_is_rejected() {
+        let root = std::env::temp_dir().join(format!("black-hole-path-test-{}", std::process::id()));
+        let allowed = root.join("static");
+        let outside = root.join("outside");
+        fs::create_dir_all(&allowed).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        fs::write(outside.join("secret.txt"), "secret").unwrap();
+        fs::write(allowed.join("app.js"), "ok").unwrap();
+        std::os::unix::fs::symlink(outside.join("secret.txt"), allowed.join("secret.txt")).unwrap();
+
+        let allowed_str = allowed.to_str().unwrap();
+        let inside_ok = is_path_within_allowed_dirs(&allowed.join("app.js"), allowed_str);
+        let missing_ok = is_path_within_allowed_dirs(&allowed.join("new.js"), allowed_str);
+        let escape_ok = is_path_within_allowed_dirs(&allowed.join("secret.txt"), allowed_str);
+        fs::remove_dir_all(&root).unwrap();
+
+        assert!(inside_ok);
+        assert!(missing_ok);
+        assert!(!escape_ok);
+    }
+
+    fn encoding_headers(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(axum::http::header::ACCEPT_ENCODING, value.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn accept_encoding_respects_zero_quality() {
+        let accept = compression::AcceptEncoding::parse("gzip;q=0, br");
+        assert!(!accept.accepts(compression::Encoding::Gzip));
+        assert!(accept.accepts(compression::Encoding::Brotli));
+        assert_eq!(accept.preferred(), Some(compression::Encoding::Brotli));
+
+        let headers = encoding_headers("br;q=0, gzip;q=0");
+        assert_eq!(compression::choose_encoding(&headers), None);
+        assert!(!compression::accepts(&headers, compression::Encoding::Gzip));
+    }
+
+    #[test]
+    fn accept_encoding_wildcard_matches_unlisted_codings() {
+        let accept = compression::AcceptEncoding::parse("*");
+        assert!(accept.accepts(compression::Encoding::Gzip));
+        assert_eq!(accept.preferred(), Some(compression::Encoding::Brotli));
+
+        // 显式列出的编码优先于 `*`
+        let accept = compression::AcceptEncoding::parse("*;q=0.5, br;q=0");
+        assert!(!accept.accepts(compression::Encoding::Brotli));
+        assert_eq!(accept.preferred(), Some(compression::Encoding::Gzip));
+
+        let accept = compression::AcceptEncoding::parse("*;q=0");
+        assert_eq!(accept.preferred(), None);
+        assert_eq!(accept.quality("identity"), 0.0);
+    }
+
+    #[test]
+    fn empty_accept_encoding_means_identity_only() {
+        let accept = compression::AcceptEncoding::parse("");
+        assert_eq!(accept.preferred(), None);
+        assert_eq!(accept.quality("identity"), 1.0);
+        assert_eq!(compression::choose_encoding(&HeaderMap::new()), None);
+        assert!(!compression::accepts(&HeaderMap::new(), compression::Encoding::Gzip));
+    }
+
+    #[test]
+    fn accept_encoding_prefers_higher_quality_and_identity() {
+        let accept = compression::AcceptEncoding::parse("br;q=0.5, GZIP;q=0.8");
+        assert_eq!(accept.preferred(), Some(compression::Encoding::Gzip));
+
+        let accept = compression::AcceptEncoding::parse("gzip;q=0.2, identity");
+        assert_eq!(accept.preferred(), None);
+
+        // 子串匹配曾将 `x-gzip-foo` 误判为gzip
+        let accept = compression::AcceptEncoding::parse("x-gzip-foo, deflate");
+        assert!(!accept.accepts(compression::Encoding::Gzip));
+    }
+
+    fn compression_enabled() -> CompressionConfig {
+        CompressionConfig {
+            enabled: true,
+            min_bytes: 16,
+            ..CompressionConfig::default()
+        }
+    }
+
+    fn javascript_response_headers() -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(axum::http::header::CONTENT_TYPE, "application/javascript".parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn compressed_response_sets_vary() {
+        let mut headers = javascript_response_headers();
+        let body = compression::encode_body(&compression_enabled(), &encoding_headers("gzip"), &mut headers, vec![b'a'; 64]);
+        assert_eq!(headers.get(axum::http::header::CONTENT_ENCODING).unwrap(), "gzip");
+        assert_eq!(headers.get(axum::http::header::VARY).unwrap(), "Accept-Encoding");
+        assert!(body.len() < 64);
+    }
+
+    #[test]
+    fn uncompressed_negotiated_response_sets_vary() {
+        // 客户端不接受压缩时返回原始内容，但同一地址对其他客户端会压缩
+        let mut headers = javascript_response_headers();
+        compression::encode_body(&compression_enabled(), &HeaderMap::new(), &mut headers, vec![b'a'; 64]);
+        assert!(headers.get(axum::http::header::CONTENT_ENCODING).is_none());
+        assert_eq!(headers.get(axum::http::header::VARY).unwrap(), "Accept-Encoding");
+    }
+
+    #[test]
+    fn vary_is_not_set_without_negotiation() {
+        let mut headers = javascript_response_headers();
+        compression::encode_body(&CompressionConfig::default(), &encoding_headers("gzip"), &mut headers, vec![b'a'; 64]);
+        assert!(headers.get(axum::http::header::VARY).is_none());
+
+        let mut headers = HeaderMap::new();
+        headers.insert(axum::http::header::CONTENT_TYPE, "image/png".parse().unwrap());
+        compression::encode_body(&compression_enabled(), &encoding_headers("gzip"), &mut headers, vec![b'a'; 64]);
+        assert!(headers.get(axum::http::header::VARY).is_none());
+    }
+
+    #[test]
+    fn vary_merges_with_existing_values() {
+        let mut headers = HeaderMap::new();
+        headers.insert(axum::http::header::VARY, "Origin".parse().unwrap());
+        compression::set_vary_accept_encoding(&mut headers);
+        compression::set_vary_accept_encoding(&mut headers);
+        assert_eq!(headers.get(axum::http::header::VARY).unwrap(), "Origin, Accept-Encoding");
+
+        let mut headers = HeaderMap::new();
+        headers.insert(axum::http::header::VARY, "accept-encoding".parse().unwrap());
+        compression::set_vary_accept_encoding(&mut headers);
+        assert_eq!(headers.get(axum::http::header::VARY).unwrap(), "accept-encoding");
+    }
+
+    #[test]
+    fn compressed_index_sets_vary() {
+        let index = CachedIndex::new("<html>".repeat(100), &compression_enabled());
+        let (headers, _) = index.response_parts(&encoding_headers("br"));
+        assert_eq!(headers.get(axum::http::header::CONTENT_ENCODING).unwrap(), "br");
+        assert_eq!(headers.get(axum::http::header::VARY).unwrap(), "Accept-Encoding");
+
+        let index = CachedIndex::new("<html>".repeat(100), &CompressionConfig::default());
+        let (headers, _) = index.response_parts(&encoding_headers("br"));
+        assert!(headers.get(axum::http::header::VARY).is_none());
+    }
+
+    /// 测试用的临时目录，每个测试使用不同的名称
+    fn temp_root(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("black-hole-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        root
+    }
+
+    /// 使用指定缓存目录创建应用状态，proxy_extra追加到 `[proxy]` 段
+    fn test_state(cache_dir: &Path, proxy_extra: &str) -> AppState {
+        fs::create_dir_all(cache_dir).unwrap();
+        let config: Config = toml::from_str(&format!(
+            r#"
+            [proxy]
+            enabled = false
+            static_dir = "{}"
+            cache_dir = "{}"
+            {}
+
+            [log]
+            enabled = false
+            level = "info"
+
+            [server]
+            port = 8080
+            host = "localhost"
+            "#,
+            cache_dir.with_file_name("static").display(),
+            cache_dir.display(),
+            proxy_extra
+        ))
+        .unwrap();
+        let metrics = std::sync::Arc::new(metrics::Metrics::new(config.metrics.latency_buckets.clone()));
+        build_state(&config, "config.toml", metrics).unwrap()
+    }
+
+    /// 模拟 `GET /static/<path>` 请求
+    async fn get_static(state: &AppState, path: &str) -> Response {
+        handle_static_request(
+            Path(path.to_string()),
+            State(state.clone()),
+            axum::extract::RawQuery(None),
+            format!("/static/{}", path).parse().unwrap(),
+            HeaderMap::new(),
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn package_and_version_cannot_escape_cache_dir() {
+        let root = temp_root("traversal");
+        let cache_dir = root.join("a").join("b").join("cache");
+        fs::create_dir_all(root.join("etc")).unwrap();
+        fs::write(root.join("etc").join("hostname"), "secret").unwrap();
+        let state = test_state(&cache_dir, "");
+
+        // 包名 `../..` 和版本 `..` 拼接后正好指向 root/etc/hostname
+        assert!(cache_dir.join("../..").join("..").join("etc/hostname").exists());
+        let response = get_static(&state, "../..@../etc/hostname").await;
+        let scoped = get_static(&state, "@x/..@1.0.0/../../../etc/hostname").await;
+        let dot_version = get_static(&state, "react@./../../../etc/hostname").await;
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(scoped.status(), StatusCode::FORBIDDEN);
+        assert_eq!(dot_version.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn absolute_file_path_is_rejected_without_normalization() {
+        let root = temp_root("absolute");
+        let secret = root.join("hostname");
+        fs::write(&secret, "secret").unwrap();
+        let state = test_state(&root.join("cache"), "normalize_paths = false");
+
+        let response = get_static(&state, &format!("react@1.0.0/{}", secret.display())).await;
+        let dot_segment = get_static(&state, "react@1.0.0/./index.js").await;
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(dot_segment.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn unsafe_package_keys_are_rejected() {
+        assert!(is_safe_package_key("react", "18.2.0"));
+        assert!(is_safe_package_key("@highlightjs/cdn-assets", "11.9.0"));
+        assert!(!is_safe_package_key("../..", ".."));
+        assert!(!is_safe_package_key("react", "."));
+        assert!(!is_safe_package_key("react", ""));
+        assert!(!is_safe_package_key("@scope/", "1.0.0"));
+        assert!(!is_safe_package_key("a/b/c", "1.0.0"));
+        assert!(!is_safe_package_key("react", "1.0.0/.."));
+        assert!(normalize_file_path("/etc/hostname", false).is_none());
+        assert!(normalize_file_path("dist//a.js", false).is_none());
+        assert_eq!(normalize_file_path("/dist//a.js", true).as_deref(), Some("dist/a.js"));
+    }
+
+    #[test]
+    fn new_path_within_checks_nearest_existing_ancestor() {
+        let fs = fake_fs(&[("cache", "/srv/cache"), ("cache/react", "/srv/cache/react"), ("/", "/")]);
+        assert!(is_new_path_within(Path::new("cache/react/18.2.0/index.js"), Path::new("cache"), &fs));
+        assert!(is_new_path_within(Path::new("cache/vue/3.0.0/index.js"), Path::new("cache"), &fs));
+        assert!(!is_new_path_within(Path::new("/etc/hostname"), Path::new("cache"), &fs));
+        assert!(!is_new_path_within(Path::new("cache/react/.."), Path::new("cache"), &fs));
+
+        // 已存在的部分是指向目录外的符号链接
+        let fs = fake_fs(&[("cache", "/srv/cache"), ("cache/evil", "/etc")]);
+        assert!(!is_new_path_within(Path::new("cache/evil/1.0.0/passwd"), Path::new("cache"), &fs));
+    }
+
+    async fn body_bytes(response: Response) -> Vec<u8> {
+        axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap().to_vec()
+    }
+
+    /// 按download_to_cache的方式写入一个缓存条目，compressed时以gzip存储
+    async fn write_test_entry(state: &AppState, cached_file: &Path, content: &[u8], compressed: bool) {
+        let mut metadata = sidecar::CacheMetadata::new("application/javascript", content);
+        metadata.compressed = compressed;
+        let stored = if compressed {
+            compression::compress(content, compression::Encoding::Gzip).unwrap()
+        } else {
+            content.to_vec()
+        };
+        write_cache_file(state, "react", "1.0.0", cached_file, &stored, &metadata).await;
+    }
+
+    #[tokio::test]
+    async fn gzip_cached_file_without_metadata_is_a_miss() {
+        let root = temp_root("no-sidecar");
+        let state = test_state(&root.join("cache"), "");
+        let cached_file = cache_file_path(&state, "react", "1.0.0", "index.js");
+        fs::create_dir_all(cached_file.parent().unwrap()).unwrap();
+        // 压缩文件已重命名就位、元数据尚未写入时的状态
+        fs::write(&cached_file, compression::compress(b"console.log(1)", compression::Encoding::Gzip).unwrap()).unwrap();
+
+        let missing = cached_file_response(&state, &HeaderMap::new(), "react@1.0.0/index.js", &cached_file).await;
+        let meta_path = sidecar::meta_path(&state.config.proxy.cache_dir, &cached_file).unwrap();
+        fs::create_dir_all(meta_path.parent().unwrap()).unwrap();
+        fs::write(&meta_path, b"{\"content_type\": ").unwrap();
+        let corrupt = cached_file_response(&state, &HeaderMap::new(), "react@1.0.0/index.js", &cached_file).await;
+        fs::remove_dir_all(&root).unwrap();
+
+        assert!(missing.is_none());
+        assert!(corrupt.is_none());
+    }
+
+    #[tokio::test]
+    async fn cached_file_without_metadata_is_served_and_backfilled() {
+        let root = temp_root("legacy-entry");
+        // 代理关闭时只能返回磁盘上已有的文件
+        let state = test_state(&root.join("cache"), "");
+        let cached_file = cache_file_path(&state, "react", "1.0.0", "index.js");
+        let archive = cache_file_path(&state, "react", "1.0.0", "react.tgz");
+        let archive_content = compression::compress(b"tarball", compression::Encoding::Gzip).unwrap();
+        fs::create_dir_all(cached_file.parent().unwrap()).unwrap();
+        fs::write(&cached_file, b"console.log(1)").unwrap();
+        fs::write(&archive, &archive_content).unwrap();
+
+        let response = get_static(&state, "react@1.0.0/index.js").await;
+        let status = response.status();
+        let body = body_bytes(response).await;
+        let archive_response = get_static(&state, "react@1.0.0/react.tgz").await;
+        let archive_status = archive_response.status();
+        let archive_body = body_bytes(archive_response).await;
+        let mut backfilled = None;
+        for _ in 0..50 {
+            backfilled = sidecar::read(&state.config.proxy.cache_dir, &cached_file).await;
+            if backfilled.is_some() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, b"console.log(1)");
+        assert_eq!(archive_status, StatusCode::OK);
+        assert_eq!(archive_body, archive_content);
+        let backfilled = backfilled.expect("metadata was not backfilled");
+        assert!(!backfilled.compressed);
+        assert!(backfilled.matches(b"console.log(1)"));
+    }
+
+    #[tokio::test]
+    async fn compressed_cache_entry_round_trips() {
+        let root = temp_root("compressed-entry");
+        let state = test_state(&root.join("cache"), "compress_cache = true");
+        let cached_file = cache_file_path(&state, "react", "1.0.0", "index.js");
+        let content = b"console.log('hello');".repeat(10);
+        write_test_entry(&state, &cached_file, &content, true).await;
+
+        let plain = cached_file_response(&state, &HeaderMap::new(), "react@1.0.0/index.js", &cached_file)
+            .await
+            .unwrap();
+        let gzip = cached_file_response(&state, &encoding_headers("gzip"), "react@1.0.0/index.js", &cached_file)
+            .await
+            .unwrap();
+        let gzip_encoding = gzip.headers().get(axum::http::header::CONTENT_ENCODING).cloned();
+        let gzip_body = body_bytes(gzip).await;
+        let plain_encoding = plain.headers().get(axum::http::header::CONTENT_ENCODING).cloned();
+        let plain_body = body_bytes(plain).await;
+        fs::remove_dir_all(&root).unwrap();
+
+        assert!(plain_encoding.is_none());
+        assert_eq!(plain_body, content);
+        assert_eq!(gzip_encoding.unwrap(), "gzip");
+        assert_eq!(compression::gunzip(&gzip_body).unwrap(), content);
+    }
+
+    #[tokio::test]
+    async fn metadata_claiming_gzip_for_plain_content_is_a_miss() {
+        let root = temp_root("gzip-mismatch");
+        let state = test_state(&root.join("cache"), "");
+        let cached_file = cache_file_path(&state, "react", "1.0.0", "index.js");
+        write_test_entry(&state, &cached_file, b"console.log(1)", false).await;
+        // 元数据已更新为gzip、文件内容仍是旧的原文
+        let mut metadata = sidecar::read(&state.config.proxy.cache_dir, &cached_file).await.unwrap();
+        metadata.compressed = true;
+        sidecar::write(&state.config.proxy.cache_dir, &cached_file, &metadata).await.unwrap();
+
+        let plain = cached_file_response(&state, &HeaderMap::new(), "react@1.0.0/index.js", &cached_file).await;
+        let gzip = cached_file_response(&state, &encoding_headers("gzip"), "react@1.0.0/index.js", &cached_file).await;
+        fs::remove_dir_all(&root).unwrap();
+
+        assert!(plain.is_none());
+        assert!(gzip.is_none());
+    }
+
+    #[tokio::test]
+    async fn changing_storage_format_replaces_entry_consistently() {
+        let root = temp_root("format-change");
+        let state = test_state(&root.join("cache"), "");
+        let cached_file = cache_file_path(&state, "react", "1.0.0", "index.js");
+        write_test_entry(&state, &cached_file, b"old", false).await;
+        write_test_entry(&state, &cached_file, b"new content", true).await;
+
+        let metadata = sidecar::read(&state.config.proxy.cache_dir, &cached_file).await.unwrap();
+        let stored = fs::read(&cached_file).unwrap();
+        let response = cached_file_response(&state, &HeaderMap::new(), "react@1.0.0/index.js", &cached_file)
+            .await
+            .unwrap();
+        let body = body_bytes(response).await;
+        fs::remove_dir_all(&root).unwrap();
+
+        assert!(metadata.compressed);
+        assert!(stored.starts_with(&GZIP_MAGIC));
+        assert_eq!(body, b"new content");
+    }
+
+    #[tokio::test]
+    async fn access_flush_only_updates_last_accessed() {
+        let root = temp_root("flush");
+        let state = test_state(&root.join("cache"), "compress_cache = true");
+        let cache_dir = state.config.proxy.cache_dir.clone();
+        let cached_file = cache_file_path(&state, "react", "1.0.0", "index.js");
+        write_test_entry(&state, &cached_file, b"console.log(1)", true).await;
+        let before = sidecar::read(&cache_dir, &cached_file).await.unwrap();
+
+        state.access.record(&cached_file, &before);
+        state.access.flush(&cache_dir, &state.cache_locks).await;
+        let after = sidecar::read(&cache_dir, &cached_file).await.unwrap();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert!(after.last_accessed.is_some());
+        assert_eq!(after.etag, before.etag);
+        assert_eq!(after.cached_at, before.cached_at);
+        assert!(after.compressed);
+    }
+
+    #[tokio::test]
+    async fn access_flush_skips_replaced_entries() {
+        let root = temp_root("flush-replaced");
+        let state = test_state(&root.join("cache"), "");
+        let cache_dir = state.config.proxy.cache_dir.clone();
+        let cached_file = cache_file_path(&state, "react", "1.0.0", "index.js");
+        write_test_entry(&state, &cached_file, b"old", false).await;
+        let old = sidecar::read(&cache_dir, &cached_file).await.unwrap();
+        state.access.record(&cached_file, &old);
+
+        // 命中之后、写入访问时间之前，条目被重新下载为gzip存储的新内容
+        write_test_entry(&state, &cached_file, b"new content", true).await;
+        state.access.flush(&cache_dir, &state.cache_locks).await;
+        let after = sidecar::read(&cache_dir, &cached_file).await.unwrap();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert!(after.compressed);
+        assert_ne!(after.etag, old.etag);
+        assert!(after.last_accessed.is_none());
+    }
+
+    #[tokio::test]
+    async fn access_flush_waits_for_cache_lock() {
+        let root = temp_root("flush-lock");
+        let state = test_state(&root.join("cache"), "");
+        let cache_dir = state.config.proxy.cache_dir.clone();
+        let cached_file = cache_file_path(&state, "react", "1.0.0", "index.js");
+        write_test_entry(&state, &cached_file, b"console.log(1)", false).await;
+        let metadata = sidecar::read(&cache_dir, &cached_file).await.unwrap();
+        state.access.record(&cached_file, &metadata);
+
+        let guard = state.cache_locks.lock(&cached_file).await;
+        let blocked = tokio::time::timeout(Duration::from_millis(50), state.access.flush(&cache_dir, &state.cache_locks)).await;
+        let unflushed = sidecar::read(&cache_dir, &cached_file).await.unwrap();
+        drop(guard);
+        fs::remove_dir_all(&root).unwrap();
+
+        assert!(blocked.is_err());
+        assert!(unflushed.last_accessed.is_none());
+    }
+
+    /// 缓存目录（包括 `.blobs`，不包括元数据）实际占用的字节数，硬链接只计算一次
+    #[cfg(unix)]
+    fn actual_disk_usage(dir: &Path) -> u64 {
+        use std::os::unix::fs::MetadataExt;
+        fn walk(path: &Path, seen: &mut std::collections::HashSet<u64>, total: &mut u64) {
+            for entry in fs::read_dir(path).unwrap().flatten() {
+                let metadata = entry.metadata().unwrap();
+                if metadata.is_dir() {
+                    if entry.file_name() != sidecar::META_DIR {
+                        walk(&entry.path(), seen, total);
+                    }
+                } else if seen.insert(metadata.ino()) {
+                    *total += metadata.len();
+                }
+            }
+        }
+        let mut total = 0;
+        walk(dir, &mut std::collections::HashSet::new(), &mut total);
+        total
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn eviction_with_dedup_frees_blobs() {
+        let root = temp_root("dedup-eviction");
+        let cache_dir = root.join("cache");
+        let mut state = test_state(&cache_dir, "dedup = true");
+        let shared = vec![b'a'; 1000];
+        let unique = vec![b'b'; 1000];
+        for (version, content) in [("1.0.0", &shared), ("1.0.1", &shared), ("2.0.0", &unique)] {
+            let cached_file = cache_file_path(&state, "react", version, "index.js");
+            write_cache_file(&state, "react", version, &cached_file, content, &sidecar::CacheMetadata::new("application/javascript", content)).await;
+        }
+        // 之前遗留的、不再被任何缓存文件链接的blob
+        let orphan = cache_dir.join(dedup::BLOB_DIR).join("ff").join("ff".repeat(32));
+        fs::create_dir_all(orphan.parent().unwrap()).unwrap();
+        fs::write(&orphan, vec![b'c'; 500]).unwrap();
+        let before = actual_disk_usage(&cache_dir);
+
+        // 三个缓存文件只占用两份内容，上限1500需要释放其中一份
+        state.config.proxy.max_cache_bytes = 1500;
+        let report = eviction::run_in_background(state.config.proxy.clone(), state.cache_locks.clone()).await;
+        let after = actual_disk_usage(&cache_dir);
+        let remaining_links: Vec<u64> = fs::read_dir(cache_dir.join(dedup::BLOB_DIR))
+            .unwrap()
+            .flatten()
+            .flat_map(|shard| fs::read_dir(shard.path()).unwrap().flatten())
+            .map(|blob| cache_walk::link_count(&blob.path()))
+            .collect();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(before, 2500);
+        assert!(after <= 1500, "cache still uses {} bytes", after);
+        assert_eq!(report.bytes_reclaimed, before - after);
+        assert!(report.blobs_removed >= 2);
+        assert!(remaining_links.iter().all(|links| *links > 1));
+    }
+
+    /// 收集日志输出的Writer
+    #[derive(Clone, Default)]
+    struct CapturedLogs(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for CapturedLogs {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn logs_are_redacted() {
+        let root = temp_root("redact");
+        let state = test_state(&root.join("cache"), "proxy_disabled_redirect = true");
+        let logs = CapturedLogs::default();
+        let writer = logs.clone();
+        let redactor = redact::Redactor::new(&["secret-[^/]+".to_string()]).unwrap();
+        let subscriber = tracing_subscriber::fmt()
+            .with_ansi(false)
+            .with_writer(redact::RedactingMakeWriter::new(redactor, move || writer.clone()))
+            .finish();
+        let guard = tracing::subscriber::set_default(subscriber);
+        let response = get_static(&state, "react@1.0.0/secret-abc123/index.js").await;
+        drop(guard);
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(response.status(), StatusCode::FOUND);
+        let output = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+        // 请求日志、span字段、缓存路径和上游重定向地址都要脱敏
+        assert!(output.contains("Received request"), "{}", output);
+        assert!(output.contains("Checking cache file"), "{}", output);
+        assert!(output.contains("redirecting to upstream"), "{}", output);
+        assert!(output.contains("***"), "{}", output);
+        assert!(!output.contains("abc123"), "{}", output);
+    }
+
+    /// 完整下载永远不返回、只有Range请求能完成的上游
+    struct StalledFullDownload;
+
+    impl upstream::UpstreamResolver for StalledFullDownload {
+        fn url(&self, package_name: &str, version: &str, file_path: &str) -> String {
+            format!("http://upstream.test/{}@{}/{}", package_name, version, file_path)
+        }
+
+        fn parse_url(&self, _url: &str) -> Option<(String, String, String)> {
+            None
+        }
+
+        fn fetch<'a>(&'a self, _package_name: &'a str, _version: &'a str, _file_path: &'a str) -> upstream::FetchFuture<'a> {
+            Box::pin(std::future::pending())
+        }
+
+        fn fetch_range<'a>(
+            &'a self,
+            _package_name: &'a str,
+            _version: &'a str,
+            _file_path: &'a str,
+            _range: &'a str,
+        ) -> Option<upstream::RangeFuture<'a>> {
+            Some(Box::pin(async {
+                Ok(upstream::UpstreamRangeResponse {
+                    status: 206,
+                    content_range: Some("bytes 0-3/100".to_string()),
+                    content: axum::body::Bytes::from_static(b"abcd"),
+                })
+            }))
+        }
+    }
+
+    // 多线程运行时下后台下载任务会立即开始，才能暴露名额的先后顺序
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn range_passthrough_is_not_blocked_by_background_download() {
+        let root = temp_root("range-permit");
+        let mut state = test_state(&root.join("cache"), "range_passthrough = true\nmax_connections_per_host = 1");
+        state.config.proxy.enabled = true;
+        state.resolver = std::sync::Arc::new(StalledFullDownload);
+        let mut headers = HeaderMap::new();
+        headers.insert(axum::http::header::RANGE, "bytes=0-3".parse().unwrap());
+        let request = handle_static_request(
+            Path("react@1.0.0/index.js".to_string()),
+            State(state.clone()),
+            axum::extract::RawQuery(None),
+            "/static/react@1.0.0/index.js".parse().unwrap(),
+            headers,
+        );
+        let response = tokio::time::timeout(Duration::from_secs(5), request).await;
+        fs::remove_dir_all(&root).unwrap();
+
+        // 主机只有一个名额，后台的完整下载不能先占住它
+        let response = response.expect("range request waited for the background download");
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(body_bytes(response).await, b"abcd");
+    }
+
+    /// 提供固定文件列表的上游，所有文件内容相同，package.json除外
+    struct ListingUpstream;
+
+    impl upstream::UpstreamResolver for ListingUpstream {
+        fn url(&self, package_name: &str, version: &str, file_path: &str) -> String {
+            format!("http://upstream.test/{}@{}/{}", package_name, version, file_path)
+        }
+
+        fn parse_url(&self, _url: &str) -> Option<(String, String, String)> {
+            None
+        }
+
+        fn fetch<'a>(&'a self, package_name: &'a str, version: &'a str, file_path: &'a str) -> upstream::FetchFuture<'a> {
+            let content: &'static [u8] = if file_path == "package.json" {
+                br#"{"main": "lib/index.js", "files": ["dist", "*.md", "!dist/*.map", "lib/index.js", "types/"]}"#
+            } else {
+                b"content"
+            };
+            let final_url = self.url(package_name, version, file_path);
+            Box::pin(async move {
+                Ok(upstream::UpstreamResponse {
+                    content: axum::body::Bytes::from_static(content),
+                    final_url,
+                    content_type: None,
+                })
+            })
+        }
+
+        fn list_files<'a>(&'a self, _package_name: &'a str, _version: &'a str) -> Option<upstream::ListFuture<'a>> {
+            Some(Box::pin(async {
+                Ok(["package.json", "README.md", "lib/index.js", "dist/a.js", "dist/a.js.map", "dist/sub/b.js", "src/a.js"]
+                    .iter()
+                    .map(|file| file.to_string())
+                    .collect())
+            }))
+        }
+    }
+
+    #[tokio::test]
+    async fn prefetch_expands_package_directories_and_globs() {
+        let root = temp_root("prefetch-files");
+        let mut state = test_state(&root.join("cache"), r#"no_cache_patterns = ["**/*.md"]"#);
+        state.config.proxy.enabled = true;
+        state.resolver = std::sync::Arc::new(ListingUpstream);
+        let request = prefetch::PrefetchRequest {
+            files: Vec::new(),
+            packages: vec![prefetch::PrefetchPackage {
+                name: "pkg".to_string(),
+                version: "1.0.0".to_string(),
+            }],
+        };
+        let report = prefetch::run(&state, &request).await;
+        let readme_cached = cache_file_path(&state, "pkg", "1.0.0", "README.md").exists();
+        fs::remove_dir_all(&root).unwrap();
+
+        let prefetched: Vec<&str> = report.results.iter().map(|r| r.file.as_str()).collect();
+        assert_eq!(
+            prefetched,
+            ["pkg@1.0.0/package.json", "pkg@1.0.0/lib/index.js", "pkg@1.0.0/dist/a.js", "pkg@1.0.0/dist/sub/b.js"]
+        );
+        assert_eq!(report.failed, 0);
+        let skipped: Vec<(&str, &str)> = report
+            .skipped
+            .iter()
+            .map(|s| (s.entry.as_str(), s.reason.as_str()))
+            .collect();
+        assert_eq!(
+            skipped,
+            [("pkg@1.0.0/types", "No files matched"), ("pkg@1.0.0/README.md", "Matches no_cache_patterns")]
+        );
+        assert!(!readme_cached);
+    }
+
+    async fn post_reload(state: &AppState, token: Option<&str>) -> Response {
+        let mut headers = HeaderMap::new();
+        if let Some(token) = token {
+            headers.insert("x-reload-token", token.parse().unwrap());
+        }
+        reload::handle_reload(
+            State(state.clone()),
+            axum::extract::Query(serde_json::from_str(r#"{"config": true}"#).unwrap()),
+            headers,
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn reload_checks_token_and_only_validates_config() {
+        let root = temp_root("reload");
+        let mut state = test_state(&root.join("cache"), "");
+        state.config.server.reload_token = Some(Secret("reload-secret".to_string()));
+        let missing = post_reload(&state, None).await;
+        let wrong = post_reload(&state, Some("reload-secre")).await;
+        let ok = post_reload(&state, Some("reload-secret")).await;
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(missing.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(wrong.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(ok.status(), StatusCode::OK);
+        let report: serde_json::Value = serde_json::from_slice(&body_bytes(ok).await).unwrap();
+        assert_eq!(report["config"]["applied"], false);
+    }
+
+    async fn admin_request(state: &AppState, authorization: Option<&str>) -> StatusCode {
+        use tower::ServiceExt;
+        let app = Router::new()
+            .route("/cache/gc", post(|| async { "ok" }))
+            .route_layer(axum::middleware::from_fn_with_state(state.clone(), middleware::require_admin_token));
+        let mut request = axum::extract::Request::post("/cache/gc");
+        if let Some(authorization) = authorization {
+            request = request.header(axum::http::header::AUTHORIZATION, authorization);
+        }
+        app.oneshot(request.body(axum::body::Body::empty()).unwrap())
+            .await
+            .unwrap()
+            .status()
+    }
+
+    #[tokio::test]
+    async fn admin_endpoints_fail_closed_without_token() {
+        let root = temp_root("admin");
+        let mut state = test_state(&root.join("cache"), "");
+        let unconfigured = admin_request(&state, Some("Bearer anything")).await;
+        state.config.admin.token = Some(Secret("admin-secret".to_string()));
+        let missing = admin_request(&state, None).await;
+        let wrong = admin_request(&state, Some("Bearer admin-secre")).await;
+        let ok = admin_request(&state, Some("Bearer admin-secret")).await;
+        // 配置了admin.token后/reload同样要求Bearer令牌，不再接受reload_token
+        state.config.server.reload_token = Some(Secret("reload-secret".to_string()));
+        let reload_with_reload_token = post_reload(&state, Some("reload-secret")).await.status();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(unconfigured, StatusCode::FORBIDDEN);
+        assert_eq!(missing, StatusCode::UNAUTHORIZED);
+        assert_eq!(wrong, StatusCode::UNAUTHORIZED);
+        assert_eq!(ok, StatusCode::OK);
+        assert_eq!(reload_with_reload_token, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn resolved_version_from_registry_is_validated() {
+        // 返回恶意latest版本的registry
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let registry_url = format!("http://{}", listener.local_addr().unwrap());
+        let registry = Router::new().route(
+            "/-/package/:name/dist-tags",
+            get(|| async { axum::Json(serde_json::json!({"latest": "1.0.0/../../evil@1.0.0"})) }),
+        );
+        tokio::spawn(async move { axum::serve(listener, registry).await });
+
+        let root = temp_root("resolve");
+        let cache_dir = root.join("cache");
+        let mut state = test_state(&cache_dir, &format!(r#"registry_url = "{}""#, registry_url));
+        state.config.proxy.enabled = true;
+        state.resolver = std::sync::Arc::new(ListingUpstream);
+        let response = get_static(&state, "react@*/index.js").await;
+        let written = cache_walk::collect(&state.config.proxy.cache_dir).len();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+        assert_eq!(written, 0);
+    }
+
+    #[tokio::test]
+    async fn verify_deletes_corrupt_files_with_delete_flag() {
+        let root = temp_root("verify");
+        let state = test_state(&root.join("cache"), "");
+        let cached_file = cache_file_path(&state, "react", "1.0.0", "index.js");
+        let mut results = Vec::new();
+        for (query, deleted) in [("delete=0", false), ("delete=false", false), ("delete=1", true), ("delete=true", true)] {
+            write_test_entry(&state, &cached_file, b"original", false).await;
+            fs::write(&cached_file, b"corrupted").unwrap();
+            let uri: axum::http::Uri = format!("/cache/verify?{}", query).parse().unwrap();
+            let params = axum::extract::Query::try_from_uri(&uri).unwrap();
+            let axum::Json(report) = verify::handle_cache_verify(State(state.clone()), params).await;
+            results.push((query, report.corrupt, report.deleted, cached_file.exists(), deleted));
+        }
+        let invalid: axum::http::Uri = "/cache/verify?delete=yes".parse().unwrap();
+        let rejected = axum::extract::Query::<verify::VerifyParams>::try_from_uri(&invalid).is_err();
+        fs::remove_dir_all(&root).unwrap();
+
+        for (query, corrupt, deleted_count, still_exists, deleted) in results {
+            assert_eq!(corrupt, ["react/1.0.0/index.js"], "{}", query);
+            assert_eq!(deleted_count, deleted as usize, "{}", query);
+            assert_eq!(still_exists, !deleted, "{}", query);
+        }
+        assert!(rejected);
+    }
+
+    #[tokio::test]
+    async fn prefetch_and_mirror_cannot_escape_cache_dir() {
+        let root = temp_root("traversal-entry-points");
+        let cache_dir = root.join("a").join("b").join("cache");
+        fs::create_dir_all(root.join("mirror")).unwrap();
+        fs::write(root.join("secret.js"), "secret").unwrap();
+        let state = test_state(&cache_dir, &format!(r#"mirror_dir = "{}""#, root.join("mirror").display()));
+        let request = prefetch::PrefetchRequest {
+            files: vec![
+                "../..@../etc/hostname".to_string(),
+                "@x/..@1.0.0/../../../etc/hostname".to_string(),
+                "react@./../../../etc/hostname".to_string(),
+            ],
+            packages: Vec::new(),
+        };
+        let report = prefetch::run(&state, &request).await;
+        // mirror_dir/../secret.js
+        let mirrored = get_static(&state, "..@..@1.0.0/secret.js").await;
+        let mirrored_scoped = get_static(&state, "@x/..@1.0.0/../secret.js").await;
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(report.succeeded, 0);
+        assert!(report.results.iter().all(|r| r.error.as_deref() == Some("Unsafe path")), "{:?}", report.results);
+        assert_ne!(mirrored.status(), StatusCode::OK);
+        assert_ne!(mirrored_scoped.status(), StatusCode::OK);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn dedup_shares_blobs_without_leaking_rewrites() {
+        use std::os::unix::fs::MetadataExt;
+        let root = temp_root("dedup-store");
+        let state = test_state(&root.join("cache"), "dedup = true");
+        let first = cache_file_path(&state, "react", "1.0.0", "index.js");
+        let second = cache_file_path(&state, "react", "1.0.1", "index.js");
+        write_test_entry(&state, &first, b"shared", false).await;
+        write_test_entry(&state, &second, b"shared", false).await;
+        let shared_inode = fs::metadata(&first).unwrap().ino() == fs::metadata(&second).unwrap().ino();
+        // 重新写入其中一个路径不能修改共享的blob
+        write_test_entry(&state, &first, b"changed", false).await;
+        let first_content = body_bytes(get_static(&state, "react@1.0.0/index.js").await).await;
+        let second_content = body_bytes(get_static(&state, "react@1.0.1/index.js").await).await;
+        fs::remove_dir_all(&root).unwrap();
+
+        assert!(shared_inode);
+        assert_eq!(first_content, b"changed");
+        assert_eq!(second_content, b"shared");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn ttl_eviction_uses_cached_at_for_deduplicated_files() {
+        let root = temp_root("dedup-ttl");
+        let mut state = test_state(&root.join("cache"), "dedup = true");
+        let old = cache_file_path(&state, "react", "1.0.0", "index.js");
+        let fresh = cache_file_path(&state, "react", "1.0.1", "index.js");
+        write_test_entry(&state, &old, b"shared", false).await;
+        // 模拟两天前写入的缓存：元数据和blob的修改时间都是两天前
+        let two_days_ago = std::time::SystemTime::now() - Duration::from_secs(2 * 86400);
+        let mut metadata = sidecar::read(&state.config.proxy.cache_dir, &old).await.unwrap();
+        metadata.cached_at -= 2 * 86400;
+        sidecar::write(&state.config.proxy.cache_dir, &old, &metadata).await.unwrap();
+        fs::File::options().write(true).open(&old).unwrap().set_modified(two_days_ago).unwrap();
+        // 新版本的相同内容链接到旧blob，继承了旧的修改时间
+        write_test_entry(&state, &fresh, b"shared", false).await;
+        let inherited_mtime = fs::metadata(&fresh).unwrap().modified().unwrap() <= two_days_ago;
+
+        state.config.proxy.cache_ttl_secs = 86400;
+        let report = eviction::run_in_background(state.config.proxy.clone(), state.cache_locks.clone()).await;
+        let old_exists = old.exists();
+        let fresh_exists = fresh.exists();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert!(inherited_mtime);
+        assert_eq!(report.expired, 1);
+        assert!(!old_exists);
+        assert!(fresh_exists);
+    }
+
+    #[tokio::test]
+    async fn eviction_waits_for_cache_write_lock() {
+        let root = temp_root("evict-lock");
+        let mut state = test_state(&root.join("cache"), "");
+        let cached_file = cache_file_path(&state, "react", "1.0.0", "index.js");
+        write_test_entry(&state, &cached_file, b"content", false).await;
+        state.config.proxy.cache_ttl_secs = 1;
+        let mut metadata = sidecar::read(&state.config.proxy.cache_dir, &cached_file).await.unwrap();
+        metadata.cached_at -= 10;
+        sidecar::write(&state.config.proxy.cache_dir, &cached_file, &metadata).await.unwrap();
+
+        // 持有写锁期间淘汰不能删除该文件
+        let guard = state.cache_locks.lock(&cached_file).await;
+        let gc = tokio::spawn(eviction::run_in_background(state.config.proxy.clone(), state.cache_locks.clone()));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let exists_while_locked = cached_file.exists();
+        drop(guard);
+        let report = gc.await.unwrap();
+        let exists_after = cached_file.exists();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert!(exists_while_locked);
+        assert_eq!(report.expired, 1);
+        assert!(!exists_after);
+    }
+
+    /// 经过管理接口路由组（包括鉴权中间件）发送GET请求
+    async fn admin_get(state: &AppState, uri: &str, authorization: Option<&str>) -> Response {
+        use tower::ServiceExt;
+        let mut request = axum::extract::Request::get(uri);
+        if let Some(authorization) = authorization {
+            request = request.header(axum::http::header::AUTHORIZATION, authorization);
+        }
+        admin_routes(state)
+            .with_state(state.clone())
+            .oneshot(request.body(axum::body::Body::empty()).unwrap())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn manifest_requires_admin_token_and_lists_files_without_metadata() {
+        let root = temp_root("manifest");
+        let mut state = test_state(&root.join("cache"), "");
+        state.config.admin.token = Some(Secret("admin-secret".to_string()));
+        let with_metadata = cache_file_path(&state, "react", "1.0.0", "index.js");
+        write_test_entry(&state, &with_metadata, b"compressed", true).await;
+        let without_metadata = cache_file_path(&state, "react", "1.0.0", "legacy.js");
+        fs::write(&without_metadata, b"legacy").unwrap();
+        // 没有元数据又以gzip魔数开头，返回文件时按未命中处理，清单中也不应出现
+        let ambiguous = cache_file_path(&state, "react", "1.0.0", "ambiguous.js");
+        fs::write(&ambiguous, compression::compress(b"ambiguous", compression::Encoding::Gzip).unwrap()).unwrap();
+
+        let unauthorized = admin_get(&state, "/cache/manifest", None).await.status();
+        let response = admin_get(&state, "/cache/manifest", Some("Bearer admin-secret")).await;
+        let status = response.status();
+        let manifest: serde_json::Value = serde_json::from_slice(&body_bytes(response).await).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(unauthorized, StatusCode::UNAUTHORIZED);
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            manifest,
+            serde_json::json!([
+                {"url": "/static/react@1.0.0/index.js", "integrity": manifest::integrity(b"compressed")},
+                {"url": "/static/react@1.0.0/legacy.js", "integrity": manifest::integrity(b"legacy")},
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn cache_inspection_endpoints_require_admin_token() {
+        let root = temp_root("admin-inspection");
+        let mut state = test_state(&root.join("cache"), "");
+        state.config.admin.token = Some(Secret("admin-secret".to_string()));
+        let mut statuses = Vec::new();
+        for uri in ["/cache/search?glob=react*", "/logs/recent", "/stats/coldest"] {
+            let unauthorized = admin_get(&state, uri, None).await.status();
+            let authorized = admin_get(&state, uri, Some("Bearer admin-secret")).await.status();
+            statuses.push((uri, unauthorized, authorized));
+        }
+        fs::remove_dir_all(&root).unwrap();
+
+        for (uri, unauthorized, authorized) in statuses {
+            assert_eq!(unauthorized, StatusCode::UNAUTHORIZED, "{}", uri);
+            assert_eq!(authorized, StatusCode::OK, "{}", uri);
+        }
+    }
+
+    #[tokio::test]
+    async fn cache_info_uses_the_serving_hit_rule() {
+        let root = temp_root("cache-info-hit-rule");
+        let state = test_state(&root.join("cache"), "");
+        let legacy = cache_file_path(&state, "react", "1.0.0", "legacy.js");
+        fs::create_dir_all(legacy.parent().unwrap()).unwrap();
+        fs::write(&legacy, b"legacy").unwrap();
+        let ambiguous = cache_file_path(&state, "react", "1.0.0", "ambiguous.js");
+        fs::write(&ambiguous, compression::compress(b"ambiguous", compression::Encoding::Gzip).unwrap()).unwrap();
+
+        let mut infos = Vec::new();
+        for path in ["react/1.0.0/legacy.js", "react/1.0.0/ambiguous.js"] {
+            let response = cache_info::handle_cache_info(Path(path.to_string()), State(state.clone())).await;
+            infos.push(serde_json::from_slice::<serde_json::Value>(&body_bytes(response).await).unwrap());
+        }
+        let served = get_static(&state, "react@1.0.0/ambiguous.js").await.status();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(infos[0]["cached"], true);
+        assert_eq!(infos[0]["size"], 6);
+        assert_eq!(infos[0]["etag"], sidecar::CacheMetadata::new("application/javascript", b"legacy").etag);
+        // 返回文件时按未命中处理的文件，/cache/info也不报告为已缓存
+        assert_eq!(infos[1]["cached"], false);
+        assert_ne!(served, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn peers_exchange_file_content_instead_of_x_accel_redirect() {
+        let root = temp_root("peer-x-accel");
+        let mut state = test_state(&root.join("cache"), r#"x_accel_redirect = true"#);
+        let cached_file = cache_file_path(&state, "react", "1.0.0", "index.js");
+        write_test_entry(&state, &cached_file, b"content", false).await;
+        let mut peer_headers = HeaderMap::new();
+        peer_headers.insert(peer::PEER_HEADER, axum::http::HeaderValue::from_static("1"));
+        let served = cached_file_response(&state, &peer_headers, "index.js", &cached_file).await.unwrap();
+        let served_x_accel = served.headers().contains_key("x-accel-redirect");
+        let served_body = body_bytes(served).await;
+
+        // 旧版本的实例仍可能只返回X-Accel-Redirect响应头
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        state.config.proxy.peer_cache_urls = vec![format!("http://{}", listener.local_addr().unwrap())];
+        let peer = Router::new().fallback(|| async { [("x-accel-redirect", "/_blackhole_cache/react/1.0.0/index.js")] });
+        tokio::spawn(async move { axum::serve(listener, peer).await });
+        let fetched = peer::fetch(&state, "react", "1.0.0", "index.js").await;
+        fs::remove_dir_all(&root).unwrap();
+
+        assert!(!served_x_accel);
+        assert_eq!(served_body, b"content");
+        assert!(fetched.is_none());
+    }
+
+    /// 从连接中读取，直到收到marker或连接关闭
+    async fn read_until(stream: &mut tokio::net::TcpStream, marker: &[u8]) -> Vec<u8> {
+        use tokio::io::AsyncReadExt;
+        let mut received = Vec::new();
+        let mut buf = [0u8; 1024];
+        while !received.windows(marker.len()).any(|window| window == marker) {
+            match tokio::time::timeout(Duration::from_secs(10), stream.read(&mut buf)).await {
+                Ok(Ok(n)) if n > 0 => received.extend_from_slice(&buf[..n]),
+                _ => break,
+            }
+        }
+        received
+    }
+
+    #[tokio::test]
+    async fn server_tracks_streaming_bodies_and_bounds_shutdown() {
+        use tokio::io::AsyncWriteExt;
+        let root = temp_root("server");
+        let state = test_state(&root.join("cache"), "");
+        let mut server_config = state.config.server.clone();
+        server_config.keep_alive_timeout_secs = 1;
+        server_config.shutdown_timeout_secs = 1;
+        fs::remove_dir_all(&root).unwrap();
+        let app = Router::new()
+            .route(
+                "/slow",
+                get(|| async {
+                    // 响应体在keep_alive_timeout_secs之后才发送完
+                    let (mut writer, reader) = tokio::io::duplex(64);
+                    tokio::spawn(async move {
+                        for _ in 0..3 {
+                            tokio::time::sleep(Duration::from_millis(700)).await;
+                            let _ = writer.write_all(b"chunk").await;
+                        }
+                    });
+                    axum::body::Body::from_stream(tokio_util::io::ReaderStream::new(reader))
+                }),
+            )
+            .route(
+                "/stalled",
+                get(|| async {
+                    let (writer, reader) = tokio::io::duplex(64);
+                    tokio::spawn(async move {
+                        tokio::time::sleep(Duration::from_secs(3600)).await;
+                        drop(writer);
+                    });
+                    axum::body::Body::from_stream(tokio_util::io::ReaderStream::new(reader))
+                }),
+            )
+            .route("/fast", get(|| async { "fast" }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(async move {
+            server::serve(listener, app, &server_config, async {
+                let _ = shutdown_rx.await;
+            })
+            .await
+        });
+
+        // 发送响应体期间连接不算空闲，之后仍可在同一连接上发送下一个请求
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream.write_all(b"GET /slow HTTP/1.1\r\nhost: test\r\n\r\n").await.unwrap();
+        let slow = read_until(&mut stream, b"0\r\n\r\n").await;
+        stream.write_all(b"GET /fast HTTP/1.1\r\nhost: test\r\n\r\n").await.unwrap();
+        let fast = read_until(&mut stream, b"fast").await;
+
+        // 响应体一直未发送完的请求不会让优雅退出无限等待
+        let mut stalled = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stalled.write_all(b"GET /stalled HTTP/1.1\r\nhost: test\r\n\r\n").await.unwrap();
+        let stalled_headers = read_until(&mut stalled, b"\r\n\r\n").await;
+        shutdown_tx.send(()).unwrap();
+        let stopped = tokio::time::timeout(Duration::from_secs(5), server).await;
+
+        assert!(String::from_utf8_lossy(&slow).contains("chunk"));
+        assert!(fast.ends_with(b"fast"));
+        assert!(stalled_headers.starts_with(b"HTTP/1.1 200"));
+        assert!(stopped.is_ok_and(|result| result.unwrap().is_ok()));
+    }
+
+    #[tokio::test]
+    async fn gc_evicts_least_recently_accessed_files_over_limit() {
+        let root = temp_root("gc-lru");
+        let mut state = test_state(&root.join("cache"), "");
+        let now = sidecar::unix_now();
+        let mut files = Vec::new();
+        for (version, accessed_ago) in [("1.0.0", 100), ("1.0.1", 300), ("1.0.2", 200)] {
+            let cached_file = cache_file_path(&state, "react", version, "index.js");
+            write_test_entry(&state, &cached_file, version.as_bytes(), false).await;
+            let mut metadata = sidecar::read(&state.config.proxy.cache_dir, &cached_file).await.unwrap();
+            metadata.last_accessed = Some(now - accessed_ago);
+            sidecar::write(&state.config.proxy.cache_dir, &cached_file, &metadata).await.unwrap();
+            // 淘汰取atime和元数据中较新的访问时间，刚写入的文件atime是现在，这里改为更早的时间
+            let long_ago = std::time::SystemTime::now() - Duration::from_secs(3600);
+            let times = fs::FileTimes::new().set_accessed(long_ago);
+            fs::File::options().write(true).open(&cached_file).unwrap().set_times(times).unwrap();
+            files.push(cached_file);
+        }
+
+        state.config.proxy.max_cache_files = 2;
+        let axum::Json(report) = eviction::handle_cache_gc(State(state.clone())).await;
+        let remaining: Vec<bool> = files.iter().map(|file| file.exists()).collect();
+        let sidecar_left = sidecar::meta_path(&state.config.proxy.cache_dir, &files[1]).unwrap().exists();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(report.files_removed, 1);
+        assert_eq!(report.over_file_limit, 1);
+        assert_eq!(report.bytes_reclaimed, 5);
+        assert_eq!(remaining, [true, false, true]);
+        assert!(!sidecar_left);
+    }
+
+    #[test]
+    fn env_var_references_are_expanded_in_nested_strings() {
+        // 使用一定存在的PATH，避免在并行执行的测试中修改环境变量
+        let path = std::env::var("PATH").unwrap();
+        let mut value = serde_json::json!({
+            "proxy": {
+                "upstream_url": "prefix-${PATH}-suffix",
+                "peer_cache_urls": ["${PATH}", "$PATH", "plain"],
+                "max_cache_files": 10,
+            }
+        });
+        expand_env_vars(&mut value).unwrap();
+        let mut unset = serde_json::json!({"admin": {"token": "${BLACK_HOLE_TEST_UNSET_VARIABLE}"}});
+        let error = expand_env_vars(&mut unset).unwrap_err().to_string();
+
+        assert_eq!(value["proxy"]["upstream_url"], format!("prefix-{}-suffix", path));
+        assert_eq!(value["proxy"]["peer_cache_urls"], serde_json::json!([path, "$PATH", "plain"]));
+        assert_eq!(value["proxy"]["max_cache_files"], 10);
+        assert!(error.contains("BLACK_HOLE_TEST_UNSET_VARIABLE"));
+    }
+
+    #[tokio::test]
+    async fn charset_follows_path_type_and_default_rules() {
+        let root = temp_root("charset");
+        let mut state = test_state(&root.join("cache"), "");
+        state.config.charset.types.insert("text/css".to_string(), String::new());
+        state.config.charset.paths.insert("react@1.0.0/legacy/".to_string(), "iso-8859-1".to_string());
+        for file in ["index.js", "legacy/old.js", "style.css", "logo.png"] {
+            let cached_file = cache_file_path(&state, "react", "1.0.0", file);
+            fs::create_dir_all(cached_file.parent().unwrap()).unwrap();
+            write_test_entry(&state, &cached_file, b"content", false).await;
+        }
+        let mut content_types = Vec::new();
+        for file in ["index.js", "legacy/old.js", "style.css", "logo.png"] {
+            let response = get_static(&state, &format!("react@1.0.0/{}", file)).await;
+            content_types.push(response.headers()[axum::http::header::CONTENT_TYPE].to_str().unwrap().to_string());
+        }
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(
+            content_types,
+            [
+                format!("{}; charset=utf-8", content_type_for("index.js")),
+                format!("{}; charset=iso-8859-1", content_type_for("old.js")),
+                "text/css".to_string(),
+                "image/png".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn compression_types_allowlist_matches_content_types_and_extensions() {
+        let config = CompressionConfig {
+            types: vec!["text/css".to_string(), "js".to_string()],
+            ..compression_enabled()
+        };
+        let mut encodings = Vec::new();
+        for content_type in ["text/css", "application/javascript; charset=utf-8", "application/json", "text/html"] {
+            let mut headers = HeaderMap::new();
+            headers.insert(axum::http::header::CONTENT_TYPE, content_type.parse().unwrap());
+            compression::encode_body(&config, &encoding_headers("gzip"), &mut headers, vec![b'a'; 64]);
+            encodings.push(headers.get(axum::http::header::CONTENT_ENCODING).cloned());
+        }
+
+        assert_eq!(encodings, [Some("gzip".parse().unwrap()), Some("gzip".parse().unwrap()), None, None]);
+        assert!(CompressionConfig::default().is_type_allowed("image/svg+xml"));
+        assert!(!CompressionConfig::default().is_type_allowed("image/png"));
+    }
+
+    /// 记录下载次数的上游，每次下载返回递增的内容，下载前等待一小段时间以便并发请求重叠
+    #[derive(Default)]
+    struct CountingUpstream {
+        fetches: std::sync::atomic::AtomicUsize,
+    }
+
+    impl upstream::UpstreamResolver for CountingUpstream {
+        fn url(&self, package_name: &str, version: &str, file_path: &str) -> String {
+            format!("http://upstream.test/{}@{}/{}", package_name, version, file_path)
+        }
+
+        fn parse_url(&self, _url: &str) -> Option<(String, String, String)> {
+            None
+        }
+
+        fn fetch<'a>(&'a self, package_name: &'a str, version: &'a str, file_path: &'a str) -> upstream::FetchFuture<'a> {
+            let final_url = self.url(package_name, version, file_path);
+            Box::pin(async move {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                let count = self.fetches.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1;
+                Ok(upstream::UpstreamResponse {
+                    content: axum::body::Bytes::from(format!("fetch {}", count)),
+                    final_url,
+                    content_type: None,
+                })
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn no_cache_patterns_always_fetch_from_upstream() {
+        let root = temp_root("no-cache-patterns");
+        let mut state = test_state(&root.join("cache"), r#"no_cache_patterns = ["**/*.json"]"#);
+        state.config.proxy.enabled = true;
+        let upstream = std::sync::Arc::new(CountingUpstream::default());
+        state.resolver = upstream.clone();
+        // 已有的缓存文件同样不读取
+        let stale = cache_file_path(&state, "pkg", "1.0.0", "data.json");
+        write_test_entry(&state, &stale, b"stale", false).await;
+
+        let mut bodies = Vec::new();
+        for path in ["pkg@1.0.0/data.json", "pkg@1.0.0/data.json", "pkg@1.0.0/index.js", "pkg@1.0.0/index.js"] {
+            bodies.push(String::from_utf8(body_bytes(get_static(&state, path).await).await).unwrap());
+        }
+        let stale_content = fs::read(&stale).unwrap();
+        let fetches = upstream.fetches.load(std::sync::atomic::Ordering::SeqCst);
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(bodies, ["fetch 1", "fetch 2", "fetch 3", "fetch 3"]);
+        assert_eq!(stale_content, b"stale");
+        assert_eq!(fetches, 3);
+    }
+
+    #[tokio::test]
+    async fn micro_cache_coalesces_no_cache_downloads_within_ttl() {
+        let root = temp_root("micro-cache");
+        let mut state = test_state(
+            &root.join("cache"),
+            r#"no_cache_patterns = ["**/*.json"]
+            micro_cache_ttl_ms = 60000
+            micro_cache_max_entries = 1"#,
+        );
+        state.config.proxy.enabled = true;
+        let upstream = std::sync::Arc::new(CountingUpstream::default());
+        state.resolver = upstream.clone();
+
+        // 并发请求和TTL内的重复请求共用一次下载
+        let (first, second) = tokio::join!(get_static(&state, "pkg@1.0.0/data.json"), get_static(&state, "pkg@1.0.0/data.json"));
+        let repeated = get_static(&state, "pkg@1.0.0/data.json").await;
+        let coalesced = upstream.fetches.load(std::sync::atomic::Ordering::SeqCst);
+        // 达到micro_cache_max_entries后新路径不缓存
+        get_static(&state, "pkg@1.0.0/other.json").await;
+        get_static(&state, "pkg@1.0.0/other.json").await;
+        let over_limit = upstream.fetches.load(std::sync::atomic::Ordering::SeqCst);
+        let mut bodies = Vec::new();
+        for response in [first, second, repeated] {
+            bodies.push(String::from_utf8(body_bytes(response).await).unwrap());
+        }
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(bodies, ["fetch 1", "fetch 1", "fetch 1"]);
+        assert_eq!(coalesced, 1);
+        assert_eq!(over_limit, 3);
+    }
+
+    #[tokio::test]
+    async fn static_mounts_serve_files_from_their_own_dirs() {
+        use tower::ServiceExt;
+        let root = temp_root("static-mounts");
+        for (dir, file, content) in [("assets", "app.css", "body {}"), ("vendor", "lib.js", "lib()")] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+            fs::write(root.join(dir).join(file), content).unwrap();
+        }
+        let state = test_state(
+            &root.join("cache"),
+            &format!(
+                r#"static_mounts = [{{ prefix = "/assets", dir = "{}" }}, {{ prefix = "/vendor/", dir = "{}" }}]"#,
+                root.join("assets").display(),
+                root.join("vendor").display()
+            ),
+        );
+        let app = content_routes(&state.config).with_state(state.clone());
+        let mut responses = Vec::new();
+        for uri in ["/assets/app.css", "/vendor/lib.js", "/assets/lib.js"] {
+            let request = axum::extract::Request::get(uri).body(axum::body::Body::empty()).unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            let content_type = response.headers().get(axum::http::header::CONTENT_TYPE).cloned();
+            responses.push((response.status(), content_type, body_bytes(response).await));
+        }
+        // 与已有路由冲突的挂载在校验时报错
+        let mut conflicting = state.config.clone();
+        conflicting.proxy.static_mounts[1].prefix = "/static".to_string();
+        let problems = conflicting.validate();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(responses[0].0, StatusCode::OK);
+        assert_eq!(responses[0].1.as_ref().unwrap(), "text/css; charset=utf-8");
+        assert_eq!(responses[0].2, b"body {}");
+        assert_eq!(responses[1].0, StatusCode::OK);
+        assert_eq!(responses[1].2, b"lib()");
+        // 每个挂载只能访问自己目录下的文件
+        assert_eq!(responses[2].0, StatusCode::NOT_FOUND);
+        assert!(problems.iter().any(|problem| problem.contains("'/static' is already used")));
+    }
+}
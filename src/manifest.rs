@@ -0,0 +1,89 @@
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha384};
+use tokio::fs as async_fs;
+use tracing::warn;
+
+use crate::{cache_entry_metadata, cache_info::split_info_path, cache_walk, compression, AppState};
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ManifestParams {
+    /// 只列出包名以该前缀开头的文件
+    #[serde(default)]
+    package: Option<String>,
+}
+
+/// 清单中的一项，字段与Workbox的precache清单兼容
+#[derive(Debug, Serialize)]
+pub struct ManifestEntry {
+    pub url: String,
+    pub integrity: String,
+}
+
+/// 计算内容的子资源完整性（SRI）值
+pub fn integrity(content: &[u8]) -> String {
+    format!(
+        "sha384-{}",
+        base64::engine::general_purpose::STANDARD.encode(Sha384::digest(content))
+    )
+}
+
+/// 处理 `GET /cache/manifest?package=react`，列出所有缓存文件的URL和SRI值，按URL排序；
+/// 与返回缓存文件时的命中规则一致（见cache_entry_metadata），不会返回的文件不列出
+pub async fn handle_cache_manifest(
+    State(state): State<AppState>,
+    Query(params): Query<ManifestParams>,
+) -> Json<Vec<ManifestEntry>> {
+    let cache_dir = state.config.proxy.cache_dir.clone();
+    let proxy_prefix = state.config.proxy.proxy_route_prefix();
+    let shard_chars = state.config.proxy.cache_shard_chars;
+    let mut files = tokio::task::spawn_blocking(move || cache_walk::collect(&cache_dir))
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|entry| {
+            let (package, version, file) = split_info_path(cache_walk::unsharded(&entry.relative, shard_chars)?)?;
+            let prefix = params.package.as_deref().unwrap_or("");
+            package.starts_with(prefix).then(|| {
+                let url = format!("{}/{}@{}/{}", proxy_prefix, package, version, file);
+                (url, file, entry.path)
+            })
+        })
+        .collect::<Vec<_>>();
+    files.sort();
+
+    let mut manifest = Vec::with_capacity(files.len());
+    for (url, file, path) in files {
+        let Some(metadata) = cache_entry_metadata(&state, &file, &path).await else {
+            continue;
+        };
+        let content = match async_fs::read(&path).await {
+            Ok(content) => content,
+            Err(e) => {
+                warn!("[Black Hole] Failed to read cached file {:?} for manifest: {}", path, e);
+                continue;
+            }
+        };
+        // SRI需要按客户端收到的原始内容计算
+        let content = if metadata.compressed {
+            match compression::gunzip(&content) {
+                Ok(content) => content,
+                Err(e) => {
+                    warn!("[Black Hole] Failed to decompress cached file {:?} for manifest: {}", path, e);
+                    continue;
+                }
+            }
+        } else {
+            content
+        };
+        manifest.push(ManifestEntry {
+            url,
+            integrity: integrity(&content),
+        });
+    }
+    Json(manifest)
+}
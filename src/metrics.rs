@@ -0,0 +1,209 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::fs as async_fs;
+use tracing::{debug, info, warn};
+
+/// 持久化状态文件名，保存在缓存目录根部
+const STATE_FILE: &str = ".metrics.json";
+
+/// 运行时计数器
+#[derive(Debug, Default)]
+pub struct Metrics {
+    pub cache_hits: AtomicU64,
+    pub cache_misses: AtomicU64,
+    /// 返回给客户端的响应体字节数（本地静态文件和代理文件）
+    pub bytes_served: AtomicU64,
+    /// 从上游下载的字节数
+    pub bytes_downloaded: AtomicU64,
+    pub upstream_latency: Histogram,
+}
+
+/// 固定桶的直方图，桶上限单位为秒
+#[derive(Debug, Default)]
+pub struct Histogram {
+    bounds: Vec<f64>,
+    buckets: Vec<AtomicU64>,
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+}
+
+impl Histogram {
+    pub fn new(mut bounds: Vec<f64>) -> Self {
+        bounds.retain(|b| b.is_finite());
+        bounds.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        bounds.dedup();
+        let buckets = bounds.iter().map(|_| AtomicU64::new(0)).collect();
+        Self {
+            bounds,
+            buckets,
+            count: AtomicU64::new(0),
+            sum_micros: AtomicU64::new(0),
+        }
+    }
+
+    pub fn observe(&self, duration: std::time::Duration) {
+        let seconds = duration.as_secs_f64();
+        if let Some(index) = self.bounds.iter().position(|bound| seconds <= *bound) {
+            self.buckets[index].fetch_add(1, Ordering::Relaxed);
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_micros.fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    fn render(&self, output: &mut String, name: &str, help: &str) {
+        output.push_str(&format!("# HELP {} {}\n", name, help));
+        output.push_str(&format!("# TYPE {} histogram\n", name));
+        // Prometheus的桶是累积计数
+        let mut cumulative = 0;
+        for (bound, bucket) in self.bounds.iter().zip(&self.buckets) {
+            cumulative += bucket.load(Ordering::Relaxed);
+            output.push_str(&format!("{}_bucket{{le=\"{}\"}} {}\n", name, bound, cumulative));
+        }
+        let count = self.count.load(Ordering::Relaxed);
+        output.push_str(&format!("{}_bucket{{le=\"+Inf\"}} {}\n", name, count));
+        let sum = self.sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0;
+        output.push_str(&format!("{}_sum {}\n", name, sum));
+        output.push_str(&format!("{}_count {}\n", name, count));
+    }
+}
+
+/// 计数器的快照，用于/stats输出和持久化
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct MetricsSnapshot {
+    #[serde(default)]
+    pub cache_hits: u64,
+    #[serde(default)]
+    pub cache_misses: u64,
+    #[serde(default)]
+    pub bytes_served: u64,
+    #[serde(default)]
+    pub bytes_downloaded: u64,
+}
+
+impl Metrics {
+    pub fn new(latency_buckets: Vec<f64>) -> Self {
+        Self {
+            upstream_latency: Histogram::new(latency_buckets),
+            ..Default::default()
+        }
+    }
+
+    pub fn record_hit(&self) {
+        self.cache_hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_miss(&self) {
+        self.cache_misses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_bytes_served(&self, bytes: usize) {
+        self.bytes_served.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_bytes_downloaded(&self, bytes: usize) {
+        self.bytes_downloaded.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            cache_hits: self.cache_hits.load(Ordering::Relaxed),
+            cache_misses: self.cache_misses.load(Ordering::Relaxed),
+            bytes_served: self.bytes_served.load(Ordering::Relaxed),
+            bytes_downloaded: self.bytes_downloaded.load(Ordering::Relaxed),
+        }
+    }
+
+    fn restore(&self, snapshot: &MetricsSnapshot) {
+        self.cache_hits.store(snapshot.cache_hits, Ordering::Relaxed);
+        self.cache_misses.store(snapshot.cache_misses, Ordering::Relaxed);
+        self.bytes_served.store(snapshot.bytes_served, Ordering::Relaxed);
+        self.bytes_downloaded.store(snapshot.bytes_downloaded, Ordering::Relaxed);
+    }
+
+    /// 以Prometheus文本格式输出
+    pub fn render_prometheus(&self) -> String {
+        let snapshot = self.snapshot();
+        let mut output = String::new();
+        write_counter(&mut output, "blackhole_cache_hits_total", "Number of requests served from cache", snapshot.cache_hits);
+        write_counter(&mut output, "blackhole_cache_misses_total", "Number of requests downloaded from upstream", snapshot.cache_misses);
+        write_counter(&mut output, "blackhole_bytes_served_total", "Number of body bytes served to clients", snapshot.bytes_served);
+        write_counter(&mut output, "blackhole_bytes_downloaded_total", "Number of body bytes downloaded from upstream", snapshot.bytes_downloaded);
+        self.upstream_latency.render(&mut output, "blackhole_upstream_download_seconds", "Time spent downloading files from upstream");
+        output
+    }
+}
+
+/// 每隔interval_secs秒以info级别记录该时间段内的缓存命中率，0表示不启动
+pub fn spawn_hit_ratio_logger(metrics: Arc<Metrics>, interval_secs: u64) {
+    if interval_secs == 0 {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(interval_secs));
+        interval.tick().await;
+        let mut last = metrics.snapshot();
+        loop {
+            interval.tick().await;
+            let current = metrics.snapshot();
+            let hits = current.cache_hits.saturating_sub(last.cache_hits);
+            let misses = current.cache_misses.saturating_sub(last.cache_misses);
+            let total = hits + misses;
+            if total == 0 {
+                // 空闲时不刷屏
+                debug!("[Black Hole] Cache hit ratio: no cache requests in the last {} s", interval_secs);
+            } else {
+                info!(
+                    "[Black Hole] Cache hit ratio: {:.0}% over last {} requests ({} hits, {} misses)",
+                    hits as f64 * 100.0 / total as f64,
+                    total,
+                    hits,
+                    misses
+                );
+            }
+            last = current;
+        }
+    });
+}
+
+fn write_counter(output: &mut String, name: &str, help: &str, value: u64) {
+    output.push_str(&format!("# HELP {} {}\n", name, help));
+    output.push_str(&format!("# TYPE {} counter\n", name));
+    output.push_str(&format!("{} {}\n", name, value));
+}
+
+pub fn state_file_path(cache_dir: &str) -> PathBuf {
+    Path::new(cache_dir).join(STATE_FILE)
+}
+
+/// 从状态文件恢复计数器，文件不存在时保持为0
+pub async fn load(metrics: &Metrics, path: &Path) {
+    match async_fs::read(path).await {
+        Ok(content) => match serde_json::from_slice::<MetricsSnapshot>(&content) {
+            Ok(snapshot) => {
+                metrics.restore(&snapshot);
+                info!("[Black Hole] Restored metrics from {:?}", path);
+            }
+            Err(e) => warn!("[Black Hole] Failed to parse metrics state file: {}", e),
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => warn!("[Black Hole] Failed to read metrics state file: {}", e),
+    }
+}
+
+/// 将计数器写入状态文件
+pub async fn save(metrics: &Metrics, path: &Path) {
+    let content = match serde_json::to_vec_pretty(&metrics.snapshot()) {
+        Ok(content) => content,
+        Err(e) => {
+            warn!("[Black Hole] Failed to serialize metrics: {}", e);
+            return;
+        }
+    };
+    // 先写临时文件再重命名，避免退出或崩溃时留下写了一半的状态文件
+    if let Err(e) = crate::write_atomic(path, &content).await {
+        warn!("[Black Hole] Failed to save metrics state file: {}", e);
+    }
+}
@@ -0,0 +1,63 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::OnceCell;
+
+use crate::DownloadError;
+
+/// 不写入磁盘缓存的文件（no_cache_patterns）的短时内存缓存：
+/// 同一路径的并发请求合并为一次上游下载，结果在TTL内直接复用；下载失败不缓存
+#[derive(Debug, Default)]
+pub struct MicroCache {
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    created: Instant,
+    content: Arc<OnceCell<axum::body::Bytes>>,
+}
+
+impl MicroCache {
+    /// TTL为0时直接下载；条目数达到max_entries时新路径不缓存
+    pub async fn get_or_fetch<F, Fut>(
+        &self,
+        key: &str,
+        ttl: Duration,
+        max_entries: usize,
+        fetch: F,
+    ) -> Result<axum::body::Bytes, DownloadError>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<axum::body::Bytes, DownloadError>>,
+    {
+        if ttl.is_zero() {
+            return fetch().await;
+        }
+        let content = {
+            let mut entries = self.entries.lock().unwrap();
+            let now = Instant::now();
+            entries.retain(|_, entry| now.duration_since(entry.created) < ttl);
+            match entries.get(key) {
+                Some(entry) => Some(entry.content.clone()),
+                None if entries.len() < max_entries => {
+                    let content = Arc::new(OnceCell::new());
+                    entries.insert(
+                        key.to_string(),
+                        Entry {
+                            created: now,
+                            content: content.clone(),
+                        },
+                    );
+                    Some(content)
+                }
+                None => None,
+            }
+        };
+        match content {
+            Some(content) => content.get_or_try_init(fetch).await.cloned(),
+            None => fetch().await,
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, StatusCode, Uri},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::time::Instant;
+use tracing::warn;
+
+use crate::{request_log::RequestLogEntry, sidecar, AppState};
+
+/// 记录超过log.slow_request_ms阈值的请求
+pub async fn log_slow_requests(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let threshold_ms = state.config.log.slow_request_ms;
+    if threshold_ms == 0 {
+        return next.run(request).await;
+    }
+
+    let method = request.method().clone();
+    let path = request.uri().path().to_owned();
+    let started = Instant::now();
+    let response = next.run(request).await;
+    let elapsed_ms = started.elapsed().as_millis();
+
+    if elapsed_ms >= threshold_ms as u128 {
+        warn!(
+            "[Black Hole] Slow request: {} {} -> {} in {} ms",
+            method,
+            path,
+            response.status().as_u16(),
+            elapsed_ms
+        );
+    }
+    response
+}
+
+/// 管理接口的鉴权：要求 `Authorization: Bearer <token>` 与admin.token一致，否则返回401；
+/// 未配置admin.token时管理接口全部关闭，返回403
+pub async fn require_admin_token(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let Some(expected) = &state.config.admin.token else {
+        warn!(
+            "[Black Hole] Rejected admin request, admin.token is not configured: {} {}",
+            request.method(),
+            request.uri().path()
+        );
+        return (StatusCode::FORBIDDEN, "Admin endpoints are disabled").into_response();
+    };
+    if !expected.matches(bearer_token(request.headers())) {
+        warn!(
+            "[Black Hole] Rejected unauthorized admin request: {} {}",
+            request.method(),
+            request.uri().path()
+        );
+        return unauthorized_response();
+    }
+    next.run(request).await
+}
+
+/// `Authorization: Bearer <token>` 中的令牌
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+}
+
+/// 令牌缺失或不一致时的401响应
+pub fn unauthorized_response() -> Response {
+    (
+        StatusCode::UNAUTHORIZED,
+        [(header::WWW_AUTHENTICATE, "Bearer")],
+        "Unauthorized",
+    )
+        .into_response()
+}
+
+/// 将请求记录到内存中的最近请求环形缓冲区
+pub async fn record_recent_requests(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    if !state.request_log.is_enabled() {
+        return next.run(request).await;
+    }
+
+    let method = request.method().to_string();
+    let path = state.redactor.redact(request.uri().path()).into_owned();
+    let started = Instant::now();
+    let response = next.run(request).await;
+
+    state.request_log.push(RequestLogEntry {
+        method,
+        path,
+        status: response.status().as_u16(),
+        duration_ms: started.elapsed().as_millis() as u64,
+        timestamp: sidecar::unix_now(),
+    });
+    response
+}
+
+/// 将路径开头重复的斜杠合并为一个（如 `//` -> `/`、`//static/a.js` -> `/static/a.js`），
+/// 需要在路由匹配之前执行；状态为false时不做改写
+pub async fn normalize_leading_slashes(State(enabled): State<bool>, mut request: Request, next: Next) -> Response {
+    if enabled && request.uri().path().starts_with("//") {
+        let path = format!("/{}", request.uri().path().trim_start_matches('/'));
+        let path_and_query = match request.uri().query() {
+            Some(query) => format!("{}?{}", path, query),
+            None => path,
+        };
+        let mut parts = request.uri().clone().into_parts();
+        if let Ok(path_and_query) = path_and_query.parse() {
+            parts.path_and_query = Some(path_and_query);
+            if let Ok(uri) = Uri::from_parts(parts) {
+                *request.uri_mut() = uri;
+            }
+        }
+    }
+    next.run(request).await
+}
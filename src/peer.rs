@@ -0,0 +1,63 @@
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::AppState;
+
+/// 标记来自其他实例的请求，收到该请求头的实例只返回自己已缓存的文件，不再访问上游或其他实例，避免循环
+pub const PEER_HEADER: &str = "x-black-hole-peer";
+
+/// 请求是否由其他实例发起
+pub fn is_peer_request(request_headers: &axum::http::HeaderMap) -> bool {
+    request_headers.contains_key(PEER_HEADER)
+}
+
+/// 按顺序向peer_cache_urls中的实例请求文件，返回第一个成功的响应体；都未命中时返回None
+pub async fn fetch(
+    state: &AppState,
+    package_name: &str,
+    version: &str,
+    file_path: &str,
+) -> Option<axum::body::Bytes> {
+    let proxy = &state.config.proxy;
+    for peer in &proxy.peer_cache_urls {
+        let url = format!(
+            "{}{}/{}@{}/{}",
+            peer.trim_end_matches('/'),
+            proxy.proxy_route_prefix(),
+            package_name,
+            version,
+            file_path
+        );
+        let _permit = state.host_limiter.acquire(&url).await;
+        let response = state
+            .client
+            .get(&url)
+            .header(PEER_HEADER, "1")
+            .timeout(Duration::from_secs(proxy.peer_timeout_secs.max(1)))
+            .send()
+            .await;
+        match response {
+            // 只有响应头、内容需由前端nginx发送的响应，不能当作文件内容缓存
+            Ok(response) if response.headers().contains_key("x-accel-redirect") => {
+                warn!("[Black Hole] Ignoring X-Accel-Redirect response from peer: {}", url);
+            }
+            Ok(response) if response.status() == reqwest::StatusCode::OK => {
+                let expected_length = response.content_length();
+                match response.bytes().await {
+                    // 不完整的响应体不能写入缓存
+                    Ok(content) if expected_length.is_some_and(|expected| content.len() as u64 != expected) => {
+                        warn!("[Black Hole] Truncated response from peer: {}", url);
+                    }
+                    Ok(content) => {
+                        info!("[Black Hole] Fetched from peer cache: {}", url);
+                        return Some(content);
+                    }
+                    Err(e) => warn!("[Black Hole] Failed to read peer response from {}: {}", url, e),
+                }
+            }
+            Ok(response) => info!("[Black Hole] Peer cache miss ({}): {}", response.status(), url),
+            Err(e) => warn!("[Black Hole] Peer cache request failed for {}: {}", url, e),
+        }
+    }
+    None
+}
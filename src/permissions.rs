@@ -0,0 +1,36 @@
+use std::path::Path;
+
+use crate::ProxyConfig;
+
+/// 按cache_dir_mode设置目录权限，从dir向上逐级设置直到cache_dir（不含），
+/// 使create_dir_all新建的每一级目录都生效；非Unix平台忽略
+pub async fn apply_dir_mode(config: &ProxyConfig, dir: &Path) {
+    let Some(mode) = config.cache_dir_mode else {
+        return;
+    };
+    let root = Path::new(&config.cache_dir);
+    for ancestor in dir.ancestors() {
+        if ancestor == root || !ancestor.starts_with(root) {
+            break;
+        }
+        set_mode(ancestor, mode).await;
+    }
+}
+
+/// 按cache_file_mode设置缓存文件权限；非Unix平台忽略
+pub async fn apply_file_mode(config: &ProxyConfig, file: &Path) {
+    if let Some(mode) = config.cache_file_mode {
+        set_mode(file, mode).await;
+    }
+}
+
+#[cfg(unix)]
+pub async fn set_mode(path: &Path, mode: u32) {
+    use std::os::unix::fs::PermissionsExt;
+    if let Err(e) = tokio::fs::set_permissions(path, std::fs::Permissions::from_mode(mode)).await {
+        tracing::warn!("[Black Hole] Failed to set permissions {:o} on {:?}: {}", mode, path, e);
+    }
+}
+
+#[cfg(not(unix))]
+pub async fn set_mode(_path: &Path, _mode: u32) {}
@@ -0,0 +1,410 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use serde::{Deserialize, Serialize};
+use tokio::fs as async_fs;
+use tracing::{info, warn};
+
+use crate::{
+    cache_entry_metadata, cache_file_path, cache_search::glob_to_regex, compression, download_to_cache, fetch_uncached, is_safe_package_key,
+    is_safe_path, is_within_cache_dir, normalize_file_path, resolve, AppState, ProxyMode,
+};
+
+/// 预取请求体：可以直接列出文件，也可以给出包名和版本
+#[derive(Debug, Deserialize)]
+pub struct PrefetchRequest {
+    /// `package@version/file` 形式的文件列表
+    #[serde(default)]
+    pub files: Vec<String>,
+    /// 按package.json中列出的入口文件和files预取
+    #[serde(default)]
+    pub packages: Vec<PrefetchPackage>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PrefetchPackage {
+    pub name: String,
+    pub version: String,
+}
+
+/// 单个文件的预取结果
+#[derive(Debug, Serialize)]
+pub struct PrefetchResult {
+    pub file: String,
+    pub ok: bool,
+    /// 预取前是否已在缓存中
+    pub cached: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// 没有预取的条目：匹配no_cache_patterns，或files中的目录、通配符无法展开或没有匹配的文件
+#[derive(Debug, Serialize)]
+pub struct SkippedEntry {
+    pub entry: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PrefetchReport {
+    pub succeeded: usize,
+    pub failed: usize,
+    pub results: Vec<PrefetchResult>,
+    pub skipped: Vec<SkippedEntry>,
+}
+
+/// package.json中作为入口的字段
+const ENTRY_FIELDS: &[&str] = &["main", "module", "browser", "unpkg", "jsdelivr", "style"];
+
+pub async fn handle_cache_prefetch(
+    State(state): State<AppState>,
+    Json(request): Json<PrefetchRequest>,
+) -> impl IntoResponse {
+    (StatusCode::OK, Json(run(&state, &request).await))
+}
+
+/// 依次预取请求中的文件和包
+pub async fn run(state: &AppState, request: &PrefetchRequest) -> PrefetchReport {
+    let mut results = Vec::new();
+    let mut skipped = Vec::new();
+
+    for entry in &request.files {
+        match prefetch_entry(state, entry).await {
+            Ok(result) => results.push(result),
+            Err(skip) => skipped.push(skip),
+        }
+    }
+
+    for package in &request.packages {
+        let manifest = format!("{}@{}/package.json", package.name, package.version);
+        let content = match prefetch_entry(state, &manifest).await {
+            Ok(result) => {
+                let manifest_ok = result.ok;
+                results.push(result);
+                if !manifest_ok {
+                    continue;
+                }
+                read_cached(state, &cache_file_path(state, &package.name, &package.version, "package.json")).await
+            }
+            // package.json匹配no_cache_patterns时不写入缓存，直接从上游读取
+            Err(skip) => {
+                skipped.push(skip);
+                fetch_manifest(state, package).await
+            }
+        };
+        let Some(content) = content else {
+            continue;
+        };
+
+        let (files, unexpanded) = package_files(state, package, &content).await;
+        skipped.extend(unexpanded);
+        for file in files {
+            let entry = format!("{}@{}/{}", package.name, package.version, file);
+            match prefetch_entry(state, &entry).await {
+                Ok(result) => results.push(result),
+                Err(skip) => skipped.push(skip),
+            }
+        }
+    }
+
+    for skip in &skipped {
+        info!("[Black Hole] Prefetch skipped {}: {}", skip.entry, skip.reason);
+    }
+    let succeeded = results.iter().filter(|r| r.ok).count();
+    let failed = results.len() - succeeded;
+    info!(
+        "[Black Hole] Prefetch finished: {} succeeded, {} failed, {} skipped",
+        succeeded,
+        failed,
+        skipped.len()
+    );
+
+    PrefetchReport {
+        succeeded,
+        failed,
+        results,
+        skipped,
+    }
+}
+
+/// 启动时预取proxy.warmup_files，完成后将实例标记为就绪
+pub async fn warmup(state: AppState) {
+    if !state.config.proxy.warmup_files.is_empty() {
+        info!("[Black Hole] Warming up cache with {} files", state.config.proxy.warmup_files.len());
+        let request = PrefetchRequest {
+            files: state.config.proxy.warmup_files.clone(),
+            packages: Vec::new(),
+        };
+        let report = run(&state, &request).await;
+        for result in report.results.iter().filter(|r| !r.ok) {
+            warn!("[Black Hole] Warmup failed for {}: {}", result.file, result.error.as_deref().unwrap_or(""));
+        }
+    }
+    state.ready.store(true, std::sync::atomic::Ordering::Relaxed);
+    info!("[Black Hole] Instance is ready");
+}
+
+/// 预取一个 `package@version/file` 条目，匹配no_cache_patterns的文件不预取
+async fn prefetch_entry(state: &AppState, entry: &str) -> Result<PrefetchResult, SkippedEntry> {
+    let failure = |error: String| {
+        Ok(PrefetchResult {
+            file: entry.to_string(),
+            ok: false,
+            cached: false,
+            bytes: None,
+            error: Some(error),
+        })
+    };
+
+    let Some(captures) = state.unpkg_regex.captures(entry.trim_start_matches('/')) else {
+        return failure("Expected package@version/file".to_string());
+    };
+    let package_name = captures.get(1).unwrap().as_str();
+    let version = captures.get(2).unwrap().as_str();
+    let Some(file_path) = normalize_file_path(captures.get(3).unwrap().as_str(), state.config.proxy.normalize_paths) else {
+        return failure("Unsafe path".to_string());
+    };
+    let file_path = file_path.as_str();
+    if !is_safe_path(file_path) || !is_safe_package_key(package_name, version) {
+        return failure("Unsafe path".to_string());
+    }
+    if !state.config.proxy.is_extension_allowed(file_path) {
+        return failure("File extension not allowed".to_string());
+    }
+
+    let version = state.config.proxy.aliased_version(package_name, version).unwrap_or(version);
+    let version = if resolve::needs_resolution(version) {
+        if !state.config.proxy.enabled {
+            return failure("Proxy service not enabled".to_string());
+        }
+        match resolve::resolve_version(state, package_name, version).await {
+            Ok(resolved) => resolved,
+            Err(e) => return failure(e.message),
+        }
+    } else {
+        version.to_string()
+    };
+    let version = version.as_str();
+
+    let served_path = format!("{}@{}/{}", package_name, version.trim_start_matches('@'), file_path);
+    if state.no_cache_patterns.iter().any(|pattern| pattern.is_match(&served_path)) {
+        return Err(SkippedEntry {
+            entry: entry.to_string(),
+            reason: "Matches no_cache_patterns".to_string(),
+        });
+    }
+
+    let cached_file = cache_file_path(state, package_name, version, file_path);
+    if !is_within_cache_dir(&state.config.proxy.cache_dir, &cached_file) {
+        return failure("Unsafe path".to_string());
+    }
+    if let Some(result) = cached_result(state, entry, &cached_file).await {
+        return Ok(result);
+    }
+
+    if !state.config.proxy.enabled {
+        return failure("Proxy service not enabled".to_string());
+    }
+    if state.config.proxy.mode == ProxyMode::CacheOnly {
+        return failure("Proxy is in cache_only mode".to_string());
+    }
+
+    let _guard = state.cache_locks.lock(&cached_file).await;
+    if let Some(result) = cached_result(state, entry, &cached_file).await {
+        return Ok(result);
+    }
+
+    match download_to_cache(state, package_name, version, file_path, &cached_file, None).await {
+        Ok(content) => Ok(PrefetchResult {
+            file: entry.to_string(),
+            ok: true,
+            cached: false,
+            bytes: Some(content.len()),
+            error: None,
+        }),
+        Err(e) => failure(e.message),
+    }
+}
+
+/// 不能访问上游时返回原因
+fn upstream_unavailable(state: &AppState) -> Option<&'static str> {
+    if !state.config.proxy.enabled {
+        Some("Proxy service not enabled")
+    } else if state.config.proxy.offline {
+        Some("Offline mode: upstream access is disabled")
+    } else if state.config.proxy.mode == ProxyMode::CacheOnly {
+        Some("Proxy is in cache_only mode")
+    } else {
+        None
+    }
+}
+
+/// 读取缓存文件的原始内容，compress_cache写入的文件会先解压
+async fn read_cached(state: &AppState, cached_file: &std::path::Path) -> Option<Vec<u8>> {
+    let metadata = cache_entry_metadata(state, "package.json", cached_file).await?;
+    let content = match async_fs::read(cached_file).await {
+        Ok(content) => content,
+        Err(e) => {
+            warn!("[Black Hole] Failed to read {:?}: {}", cached_file, e);
+            return None;
+        }
+    };
+    if !metadata.compressed {
+        return Some(content);
+    }
+    compression::gunzip(&content)
+        .inspect_err(|e| warn!("[Black Hole] Failed to decompress {:?}: {}", cached_file, e))
+        .ok()
+}
+
+/// 不缓存package.json时从上游读取，只用于收集要预取的文件
+async fn fetch_manifest(state: &AppState, package: &PrefetchPackage) -> Option<Vec<u8>> {
+    if let Some(reason) = upstream_unavailable(state) {
+        warn!("[Black Hole] Cannot read package.json of {}@{}: {}", package.name, package.version, reason);
+        return None;
+    }
+    match fetch_uncached(state, &package.name, &package.version, "package.json", None).await {
+        Ok(content) => Some(content.to_vec()),
+        Err(e) => {
+            warn!("[Black Hole] Failed to fetch package.json of {}@{}: {}", package.name, package.version, e.message);
+            None
+        }
+    }
+}
+
+/// 通过上游解析器获取包中的全部文件
+async fn list_package_files(state: &AppState, package: &PrefetchPackage) -> Result<Vec<String>, String> {
+    if let Some(reason) = upstream_unavailable(state) {
+        return Err(reason.to_string());
+    }
+    let Some(listing) = state.resolver.list_files(&package.name, &package.version) else {
+        return Err("Upstream does not support listing package files".to_string());
+    };
+    info!("[Black Hole] Listing files of {}@{} to expand package.json files", package.name, package.version);
+    let _permit = state
+        .host_limiter
+        .acquire(&state.resolver.url(&package.name, &package.version, ""))
+        .await;
+    listing.await.map_err(|e| e.message)
+}
+
+/// 文件已在缓存中时返回对应的预取结果，与返回缓存文件时的命中规则一致（见cache_entry_metadata）
+async fn cached_result(state: &AppState, entry: &str, cached_file: &std::path::Path) -> Option<PrefetchResult> {
+    let metadata = async_fs::metadata(cached_file).await.ok()?;
+    if !metadata.is_file() {
+        return None;
+    }
+    cache_entry_metadata(state, entry, cached_file).await?;
+    Some(PrefetchResult {
+        file: entry.to_string(),
+        ok: true,
+        cached: true,
+        bytes: Some(metadata.len() as usize),
+        error: None,
+    })
+}
+
+/// 从package.json中收集入口文件和files中列出的文件：具体文件直接使用，目录和通配符按上游的文件列表展开，
+/// `!` 开头的条目从展开结果中排除；无法展开或没有匹配文件的条目作为跳过的条目返回
+async fn package_files(state: &AppState, package: &PrefetchPackage, content: &[u8]) -> (Vec<String>, Vec<SkippedEntry>) {
+    let mut skipped = Vec::new();
+    let manifest: serde_json::Value = match serde_json::from_slice(content) {
+        Ok(manifest) => manifest,
+        Err(e) => {
+            warn!("[Black Hole] Failed to parse package.json of {}@{}: {}", package.name, package.version, e);
+            return (Vec::new(), skipped);
+        }
+    };
+
+    let mut files: Vec<String> = Vec::new();
+    for file in ENTRY_FIELDS
+        .iter()
+        .filter_map(|field| manifest.get(*field).and_then(|v| v.as_str()))
+        .map(normalize_entry)
+        .filter(|file| is_concrete_file(file))
+    {
+        push_unique(&mut files, file);
+    }
+
+    let entries: Vec<&str> = manifest
+        .get("files")
+        .and_then(|v| v.as_array())
+        .map(|files| files.iter().filter_map(|v| v.as_str()).map(normalize_entry).collect())
+        .unwrap_or_default();
+    let excluded: Vec<regex::Regex> = entries
+        .iter()
+        .filter_map(|entry| entry.strip_prefix('!'))
+        .filter_map(|entry| glob_to_regex(normalize_entry(entry)))
+        .collect();
+    let (concrete, patterns): (Vec<&str>, Vec<&str>) = entries
+        .into_iter()
+        .filter(|entry| !entry.is_empty() && !entry.starts_with('!'))
+        .partition(|entry| is_concrete_file(entry));
+
+    let mut listed: Vec<String> = concrete.iter().map(|file| file.to_string()).collect();
+    if !patterns.is_empty() {
+        match list_package_files(state, package).await {
+            Ok(package_files) => {
+                for pattern in patterns {
+                    let matched: Vec<&String> = match glob_to_regex(pattern) {
+                        Some(regex) => package_files.iter().filter(|file| matches_files_entry(&regex, file)).collect(),
+                        None => Vec::new(),
+                    };
+                    if matched.is_empty() {
+                        skipped.push(SkippedEntry {
+                            entry: format!("{}@{}/{}", package.name, package.version, pattern),
+                            reason: "No files matched".to_string(),
+                        });
+                    }
+                    listed.extend(matched.into_iter().cloned());
+                }
+            }
+            Err(reason) => {
+                for pattern in patterns {
+                    skipped.push(SkippedEntry {
+                        entry: format!("{}@{}/{}", package.name, package.version, pattern),
+                        reason: format!("Cannot expand directory or glob: {}", reason),
+                    });
+                }
+            }
+        }
+    }
+    for file in listed {
+        if !excluded.iter().any(|regex| matches_files_entry(regex, &file)) {
+            push_unique(&mut files, &file);
+        }
+    }
+    (files, skipped)
+}
+
+fn normalize_entry(entry: &str) -> &str {
+    entry.trim_start_matches("./").trim_end_matches('/')
+}
+
+/// 带扩展名且不含通配符的条目视为具体文件，其他的可能是目录
+fn is_concrete_file(entry: &str) -> bool {
+    !entry.is_empty()
+        && !entry.contains(['*', '?'])
+        && !entry.starts_with('!')
+        && std::path::Path::new(entry).extension().is_some()
+}
+
+/// 按npm的files规则，条目匹配文件本身或文件所在的任意一级目录（即目录下的所有文件）
+fn matches_files_entry(entry: &regex::Regex, file: &str) -> bool {
+    let mut path = file;
+    loop {
+        if entry.is_match(path) {
+            return true;
+        }
+        match path.rfind('/') {
+            Some(index) => path = &path[..index],
+            None => return false,
+        }
+    }
+}
+
+fn push_unique(files: &mut Vec<String>, file: &str) {
+    if !files.iter().any(|f| f == file) {
+        files.push(file.to_string());
+    }
+}
@@ -0,0 +1,80 @@
+use regex::Regex;
+use std::borrow::Cow;
+use std::io::{self, Write};
+use tracing_subscriber::fmt::MakeWriter;
+
+/// 日志脱敏：将文本中匹配log.redact_patterns的部分替换为 `***`
+#[derive(Debug, Clone, Default)]
+pub struct Redactor {
+    patterns: Vec<Regex>,
+}
+
+impl Redactor {
+    pub fn new(patterns: &[String]) -> Result<Self, regex::Error> {
+        let patterns = patterns
+            .iter()
+            .map(|pattern| Regex::new(pattern))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { patterns })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    pub fn redact<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut redacted = Cow::Borrowed(text);
+        for pattern in &self.patterns {
+            if let Cow::Owned(replaced) = pattern.replace_all(&redacted, "***") {
+                redacted = Cow::Owned(replaced);
+            }
+        }
+        redacted
+    }
+}
+
+/// 在日志写出前统一脱敏，覆盖消息、span字段以及上游错误中携带的URL和缓存路径，
+/// 不需要每个日志调用点单独处理
+#[derive(Debug, Clone)]
+pub struct RedactingMakeWriter<M> {
+    redactor: Redactor,
+    inner: M,
+}
+
+impl<M> RedactingMakeWriter<M> {
+    pub fn new(redactor: Redactor, inner: M) -> Self {
+        Self { redactor, inner }
+    }
+}
+
+impl<'a, M: MakeWriter<'a>> MakeWriter<'a> for RedactingMakeWriter<M> {
+    type Writer = RedactingWriter<'a, M::Writer>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        RedactingWriter {
+            redactor: &self.redactor,
+            inner: self.inner.make_writer(),
+        }
+    }
+}
+
+/// fmt层将每条日志格式化完整后一次性写入，因此按单次write脱敏不会截断匹配
+pub struct RedactingWriter<'a, W> {
+    redactor: &'a Redactor,
+    inner: W,
+}
+
+impl<W: Write> Write for RedactingWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.redactor.is_empty() {
+            return self.inner.write(buf);
+        }
+        let text = String::from_utf8_lossy(buf);
+        self.inner.write_all(self.redactor.redact(&text).as_bytes())?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,122 @@
+use axum::{
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::{
+    load_config,
+    middleware::{bearer_token, unauthorized_response},
+    AppState, STDIN_CONFIG_PATH,
+};
+
+/// 携带重载令牌的请求头
+const TOKEN_HEADER: &str = "x-reload-token";
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ReloadParams {
+    /// 是否同时重新读取并校验配置文件
+    #[serde(default)]
+    config: bool,
+}
+
+/// 一次重载的结果
+#[derive(Debug, Serialize)]
+pub struct ReloadReport {
+    /// 是否清空了首页缓存
+    pub index_cache_cleared: bool,
+    /// 清空的版本解析缓存条目数
+    pub tag_cache_cleared: usize,
+    /// 重新读取配置文件的结果，未请求时为None
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub config: Option<ConfigReport>,
+}
+
+/// 配置文件的重新读取结果：只校验配置，不会应用到运行中的实例，修改后仍需重启
+#[derive(Debug, Default, Serialize)]
+pub struct ConfigReport {
+    pub path: String,
+    pub valid: bool,
+    pub problems: Vec<String>,
+    /// 始终为false，表明新配置没有生效
+    pub applied: bool,
+}
+
+/// 处理 `POST /reload`：鉴权后清空内存缓存，`?config=true` 时重新读取并校验配置文件（不会应用）
+pub async fn handle_reload(
+    State(state): State<AppState>,
+    Query(params): Query<ReloadParams>,
+    headers: HeaderMap,
+) -> Response {
+    // 配置了admin.token时与其他管理接口一样鉴权，否则使用单独的重载令牌；两者都未配置时不开放
+    if let Some(expected) = &state.config.admin.token {
+        if !expected.matches(bearer_token(&headers)) {
+            warn!("[Black Hole] Rejected unauthorized admin request: POST /reload");
+            return unauthorized_response();
+        }
+    } else {
+        let Some(expected) = &state.config.server.reload_token else {
+            return (StatusCode::FORBIDDEN, "Reload is not enabled").into_response();
+        };
+        let provided = headers.get(TOKEN_HEADER).and_then(|v| v.to_str().ok());
+        if !expected.matches(provided) {
+            warn!("[Black Hole] Rejected reload request with invalid token");
+            return (StatusCode::UNAUTHORIZED, "Invalid reload token").into_response();
+        }
+    }
+
+    let index_cache_cleared = state.index_cache.write().await.take().is_some();
+    let tag_cache_cleared = {
+        let mut tag_cache = state.tag_cache.write().await;
+        let count = tag_cache.len();
+        tag_cache.clear();
+        count
+    };
+    let config = if params.config {
+        Some(reread_config(&state.config_path).await)
+    } else {
+        None
+    };
+
+    let report = ReloadReport {
+        index_cache_cleared,
+        tag_cache_cleared,
+        config,
+    };
+
+    info!(
+        "[Black Hole] Reloaded: index cache cleared {}, {} tag cache entries cleared",
+        report.index_cache_cleared, report.tag_cache_cleared
+    );
+    Json(report).into_response()
+}
+
+async fn reread_config(path: &str) -> ConfigReport {
+    // 标准输入只能读取一次
+    if path == STDIN_CONFIG_PATH {
+        return ConfigReport {
+            path: path.to_string(),
+            valid: false,
+            problems: vec!["Config was read from stdin and cannot be re-read".to_string()],
+            applied: false,
+        };
+    }
+    let problems = match load_config(path).await {
+        Ok(config) => config.validate(),
+        Err(e) => vec![format!("Failed to load config: {}", e)],
+    };
+    if problems.is_empty() {
+        info!("[Black Hole] Validated config from {} (not applied), restart to apply changes", path);
+    } else {
+        warn!("[Black Hole] Re-read config from {} with {} problem(s)", path, problems.len());
+    }
+    ConfigReport {
+        path: path.to_string(),
+        valid: problems.is_empty(),
+        problems,
+        applied: false,
+    }
+}
@@ -0,0 +1,59 @@
+use axum::{extract::State, Json};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use crate::AppState;
+
+/// 一条最近请求记录
+#[derive(Debug, Clone, Serialize)]
+pub struct RequestLogEntry {
+    pub method: String,
+    pub path: String,
+    pub status: u16,
+    pub duration_ms: u64,
+    /// 请求完成的时间（Unix秒）
+    pub timestamp: u64,
+}
+
+/// 固定容量的环形缓冲区，保存最近的请求，满时丢弃最旧的记录
+#[derive(Debug, Default)]
+pub struct RequestLog {
+    capacity: usize,
+    entries: Mutex<VecDeque<RequestLogEntry>>,
+}
+
+impl RequestLog {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    pub fn push(&self, entry: RequestLogEntry) {
+        if !self.is_enabled() {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        if entries.len() >= self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(entry);
+    }
+
+    /// 按时间先后返回当前保存的记录
+    pub fn snapshot(&self) -> Vec<RequestLogEntry> {
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.iter().cloned().collect()
+    }
+}
+
+/// 处理 `GET /logs/recent`
+pub async fn handle_recent_logs(State(state): State<AppState>) -> Json<Vec<RequestLogEntry>> {
+    Json(state.request_log.snapshot())
+}
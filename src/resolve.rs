@@ -0,0 +1,108 @@
+use axum::http::StatusCode;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tracing::{error, info};
+
+use crate::{AppState, DownloadError};
+
+/// dist-tag解析结果的内存缓存：包名 -> (版本, 解析时间)
+pub type TagCache = std::sync::Arc<tokio::sync::RwLock<HashMap<String, (String, Instant)>>>;
+
+/// registry返回的版本会拼接到缓存路径中，只接受以数字开头、由字母、数字和 `.` `-` `+` 组成的semver形式
+fn is_valid_resolved_version(version: &str) -> bool {
+    version.starts_with(|c: char| c.is_ascii_digit())
+        && version.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '+'))
+}
+
+/// 判断版本是否需要通过registry解析（目前只有通配符 `*`）
+pub fn needs_resolution(version: &str) -> bool {
+    version == "*"
+}
+
+/// 将 `*` 解析为registry中latest标签对应的具体版本
+pub async fn resolve_version(state: &AppState, package_name: &str, version: &str) -> Result<String, DownloadError> {
+    if !needs_resolution(version) {
+        return Ok(version.to_string());
+    }
+    let tag = "latest";
+    let ttl = Duration::from_secs(state.config.proxy.tag_cache_secs);
+
+    {
+        let cache = state.tag_cache.read().await;
+        if let Some((resolved, at)) = cache.get(package_name)
+            && at.elapsed() < ttl
+        {
+            return Ok(resolved.clone());
+        }
+    }
+
+    if state.config.proxy.offline {
+        return Err(DownloadError {
+            status: StatusCode::SERVICE_UNAVAILABLE,
+            message: format!("Offline mode: cannot resolve {}@{}", package_name, version),
+            location: None,
+            upstream_body: None,
+        });
+    }
+
+    let url = format!(
+        "{}/-/package/{}/dist-tags",
+        state.config.proxy.registry_url.trim_end_matches('/'),
+        package_name.replace('/', "%2F")
+    );
+    info!("[Black Hole] Resolving {}@{} via registry: {}", package_name, version, url);
+
+    let _permit = state.host_limiter.acquire(&url).await;
+    let response = state.client.get(&url).send().await.map_err(|e| {
+        error!("[Black Hole] Registry request failed: {}", e);
+        DownloadError {
+            status: StatusCode::BAD_GATEWAY,
+            message: format!("Registry request failed: {}", e),
+            location: None,
+            upstream_body: None,
+        }
+    })?;
+    if !response.status().is_success() {
+        let status = response.status();
+        error!("[Black Hole] Registry returned error: {}", status);
+        return Err(DownloadError {
+            status: StatusCode::from_u16(status.as_u16()).unwrap_or(StatusCode::BAD_GATEWAY),
+            message: format!("Registry returned error: {}", status),
+            location: None,
+            upstream_body: None,
+        });
+    }
+
+    let tags: HashMap<String, String> = response.json().await.map_err(|e| {
+        error!("[Black Hole] Failed to parse registry dist-tags: {}", e);
+        DownloadError {
+            status: StatusCode::BAD_GATEWAY,
+            message: format!("Failed to parse registry dist-tags: {}", e),
+            location: None,
+            upstream_body: None,
+        }
+    })?;
+    let resolved = tags.get(tag).cloned().ok_or_else(|| DownloadError {
+        status: StatusCode::NOT_FOUND,
+        message: format!("Package {} has no '{}' tag", package_name, tag),
+        location: None,
+        upstream_body: None,
+    })?;
+    if !is_valid_resolved_version(&resolved) {
+        error!("[Black Hole] Registry returned invalid version for {}@{}: {:?}", package_name, tag, resolved);
+        return Err(DownloadError {
+            status: StatusCode::BAD_GATEWAY,
+            message: format!("Registry returned an invalid version for {}", package_name),
+            location: None,
+            upstream_body: None,
+        });
+    }
+
+    info!("[Black Hole] Resolved {}@{} to {}", package_name, version, resolved);
+    state
+        .tag_cache
+        .write()
+        .await
+        .insert(package_name.to_string(), (resolved.clone(), Instant::now()));
+    Ok(resolved)
+}
@@ -0,0 +1,210 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::Request,
+    response::Response,
+};
+use hyper::body::{Body as HttpBody, Frame, SizeHint};
+use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
+use hyper_util::server::conn::auto;
+use hyper_util::service::TowerToHyperService;
+use std::convert::Infallible;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::watch;
+use tokio::time::Instant;
+use tower::{Service, ServiceExt};
+use tracing::{debug, error, info, warn};
+
+use crate::ServerConfig;
+
+/// 接受连接并处理HTTP/1和HTTP/2（h2c）请求，直到shutdown完成后等待所有连接结束（最长shutdown_timeout_secs）；
+/// 与axum::serve相比可以配置请求头读取超时和keep-alive，防御slow-loris类的慢速客户端
+pub async fn serve<S>(
+    listener: TcpListener,
+    app: S,
+    config: &ServerConfig,
+    shutdown: impl Future<Output = ()>,
+) -> std::io::Result<()>
+where
+    S: Service<Request, Response = Response, Error = Infallible> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    let mut builder = auto::Builder::new(TokioExecutor::new());
+    builder
+        .http1()
+        .timer(TokioTimer::new())
+        .keep_alive(config.keep_alive)
+        // hyper在等待下一个请求时同样会启动该计时器，因此它也限制了keep-alive连接的空闲时间
+        .header_read_timeout(
+            (config.header_read_timeout_secs > 0).then(|| Duration::from_secs(config.header_read_timeout_secs)),
+        );
+    builder.http2().timer(TokioTimer::new());
+    let keep_alive_timeout =
+        (config.keep_alive_timeout_secs > 0).then(|| Duration::from_secs(config.keep_alive_timeout_secs));
+
+    // signal通知所有连接开始优雅关闭；每个连接持有一个close_rx，全部释放后说明连接都已结束
+    let (signal_tx, signal_rx) = watch::channel(());
+    let (close_tx, close_rx) = watch::channel(());
+    let mut shutdown = std::pin::pin!(shutdown);
+    loop {
+        let (stream, peer) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    handle_accept_error(e).await;
+                    continue;
+                }
+            },
+            _ = &mut shutdown => break,
+        };
+
+        let activity = Arc::new(Activity::new());
+        let service = TowerToHyperService::new({
+            let activity = activity.clone();
+            app.clone()
+                .map_request(|request: Request<hyper::body::Incoming>| request.map(Body::new))
+                .map_future(move |future| {
+                    let request = activity.begin();
+                    async move {
+                        // 响应体发送完毕（或连接断开导致响应体被丢弃）时请求才算结束
+                        let response: Result<Response, Infallible> = future.await;
+                        response.map(|response| response.map(|body| Body::new(TrackedBody { body, _request: request })))
+                    }
+                })
+        });
+        let builder = builder.clone();
+        let mut signal_rx = signal_rx.clone();
+        let close_rx = close_rx.clone();
+        tokio::spawn(async move {
+            let connection = builder.serve_connection_with_upgrades(TokioIo::new(stream), service);
+            let mut connection = std::pin::pin!(connection);
+            let mut closing = false;
+            loop {
+                tokio::select! {
+                    result = connection.as_mut() => {
+                        if let Err(e) = result {
+                            debug!("[Black Hole] Connection from {} closed with error: {}", peer, e);
+                        }
+                        break;
+                    }
+                    _ = signal_rx.changed(), if !closing => {
+                        closing = true;
+                        connection.as_mut().graceful_shutdown();
+                    }
+                    _ = activity.idle(keep_alive_timeout), if !closing => {
+                        debug!("[Black Hole] Closing idle connection from {}", peer);
+                        closing = true;
+                        connection.as_mut().graceful_shutdown();
+                    }
+                }
+            }
+            drop(close_rx);
+        });
+    }
+
+    drop(listener);
+    drop(close_rx);
+    info!("[Black Hole] Waiting for open connections to finish");
+    let _ = signal_tx.send(());
+    if config.shutdown_timeout_secs == 0 {
+        close_tx.closed().await;
+    } else if tokio::time::timeout(Duration::from_secs(config.shutdown_timeout_secs), close_tx.closed())
+        .await
+        .is_err()
+    {
+        warn!(
+            "[Black Hole] Connections still open after {} s, shutting down anyway",
+            config.shutdown_timeout_secs
+        );
+    }
+    Ok(())
+}
+
+/// 与axum::serve相同：客户端在握手阶段断开之类的错误直接忽略，其他错误（如文件描述符耗尽）
+/// 等待一秒再继续accept，避免空转占满CPU
+async fn handle_accept_error(e: std::io::Error) {
+    if matches!(
+        e.kind(),
+        std::io::ErrorKind::ConnectionRefused | std::io::ErrorKind::ConnectionAborted | std::io::ErrorKind::ConnectionReset
+    ) {
+        return;
+    }
+    error!("[Black Hole] Failed to accept connection: {}", e);
+    tokio::time::sleep(Duration::from_secs(1)).await;
+}
+
+/// 单个连接上进行中的请求数和最后一个请求结束的时间，用于按keep_alive_timeout_secs关闭空闲连接
+struct Activity {
+    in_flight: AtomicUsize,
+    last_finished: Mutex<Instant>,
+}
+
+impl Activity {
+    fn new() -> Self {
+        Self {
+            in_flight: AtomicUsize::new(0),
+            last_finished: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// 请求开始，返回的guard释放时视为请求结束（包括客户端断开导致请求被取消）
+    fn begin(self: &Arc<Self>) -> ActiveRequest {
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        ActiveRequest(self.clone())
+    }
+
+    /// 连接上没有进行中的请求且空闲超过timeout时返回，没有配置超时则一直等待
+    async fn idle(&self, timeout: Option<Duration>) {
+        let Some(timeout) = timeout else {
+            return std::future::pending().await;
+        };
+        loop {
+            if self.in_flight.load(Ordering::Relaxed) > 0 {
+                tokio::time::sleep(timeout).await;
+                continue;
+            }
+            let deadline = *self.last_finished.lock().unwrap() + timeout;
+            if deadline <= Instant::now() {
+                return;
+            }
+            tokio::time::sleep_until(deadline).await;
+        }
+    }
+}
+
+struct ActiveRequest(Arc<Activity>);
+
+/// 持有ActiveRequest的响应体，流式响应（如大文件下载）发送期间连接不会被视为空闲
+struct TrackedBody {
+    body: Body,
+    _request: ActiveRequest,
+}
+
+impl HttpBody for TrackedBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Bytes>, axum::Error>>> {
+        Pin::new(&mut self.body).poll_frame(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.body.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.body.size_hint()
+    }
+}
+
+impl Drop for ActiveRequest {
+    fn drop(&mut self) {
+        *self.0.last_finished.lock().unwrap() = Instant::now();
+        self.0.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
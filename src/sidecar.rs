@@ -0,0 +1,103 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::fs as async_fs;
+use tracing::warn;
+
+/// 元数据目录，位于缓存目录根部，按缓存文件的相对路径存放
+pub const META_DIR: &str = ".meta";
+
+/// 缓存文件旁路保存的元数据
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CacheMetadata {
+    pub content_type: String,
+    pub size: u64,
+    pub etag: String,
+    /// 写入缓存的时间（Unix秒）
+    pub cached_at: u64,
+    /// 磁盘上的文件是否以gzip压缩存储
+    #[serde(default)]
+    pub compressed: bool,
+    /// 最后一次命中的时间（Unix秒），由access::AccessTracker批量写入
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_accessed: Option<u64>,
+}
+
+impl CacheMetadata {
+    /// 根据原始（未压缩）内容生成元数据
+    pub fn new(content_type: &str, content: &[u8]) -> Self {
+        Self {
+            content_type: content_type.to_string(),
+            size: content.len() as u64,
+            etag: format!("\"{}\"", crate::dedup::content_hash(content)),
+            cached_at: unix_now(),
+            compressed: false,
+            last_accessed: None,
+        }
+    }
+
+    /// 原始（未压缩）内容是否与写入时记录的大小和摘要一致
+    pub fn matches(&self, content: &[u8]) -> bool {
+        content.len() as u64 == self.size
+            && self.etag.trim_matches('"') == crate::dedup::content_hash(content)
+    }
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// 缓存文件对应的元数据路径，缓存文件不在缓存目录下时返回None
+pub fn meta_path(cache_dir: &str, cached_file: &Path) -> Option<PathBuf> {
+    let relative = cached_file.strip_prefix(cache_dir).ok()?;
+    let mut path = Path::new(cache_dir).join(META_DIR).join(relative).into_os_string();
+    path.push(".json");
+    Some(PathBuf::from(path))
+}
+
+/// 缓存目录（如某个版本目录）对应的元数据目录
+pub fn meta_dir_path(cache_dir: &str, cached_dir: &Path) -> Option<PathBuf> {
+    let relative = cached_dir.strip_prefix(cache_dir).ok()?;
+    Some(Path::new(cache_dir).join(META_DIR).join(relative))
+}
+
+pub async fn read(cache_dir: &str, cached_file: &Path) -> Option<CacheMetadata> {
+    let path = meta_path(cache_dir, cached_file)?;
+    let content = async_fs::read(&path).await.ok()?;
+    serde_json::from_slice(&content).ok()
+}
+
+/// 同步读取元数据，用于阻塞线程池中的缓存遍历
+pub fn read_sync(cache_dir: &str, cached_file: &Path) -> Option<CacheMetadata> {
+    let path = meta_path(cache_dir, cached_file)?;
+    let content = std::fs::read(&path).ok()?;
+    serde_json::from_slice(&content).ok()
+}
+
+/// 用元数据中记录的最后访问时间修正遍历得到的atime，取两者中较晚的时间
+pub fn apply_last_accessed(cache_dir: &str, entries: &mut [crate::cache_walk::CacheEntry]) {
+    for entry in entries {
+        if let Some(last_accessed) = read_sync(cache_dir, &entry.path).and_then(|metadata| metadata.last_accessed) {
+            let recorded = UNIX_EPOCH + std::time::Duration::from_secs(last_accessed);
+            entry.accessed = entry.accessed.max(recorded);
+        }
+    }
+}
+
+/// 原子地写入元数据（先写临时文件再重命名），读取者不会看到写了一半的元数据
+pub async fn write(cache_dir: &str, cached_file: &Path, metadata: &CacheMetadata) -> std::io::Result<()> {
+    let path = meta_path(cache_dir, cached_file)
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "cache file is outside cache_dir"))?;
+    if let Some(parent) = path.parent() {
+        async_fs::create_dir_all(parent).await?;
+    }
+    let content = serde_json::to_vec(metadata).map_err(std::io::Error::other)?;
+    let result = crate::write_atomic(&path, &content).await;
+    if let Err(e) = &result {
+        warn!("[Black Hole] Failed to save cache metadata: {}", e);
+    }
+    result
+}
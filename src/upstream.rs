@@ -0,0 +1,359 @@
+use axum::http::StatusCode;
+use std::future::Future;
+use std::pin::Pin;
+use tracing::{error, info};
+
+use crate::DownloadError;
+
+/// 上游返回的文件
+#[derive(Debug)]
+pub struct UpstreamResponse {
+    pub content: axum::body::Bytes,
+    /// 跟随重定向后的最终地址
+    pub final_url: String,
+    /// 上游返回的Content-Type
+    pub content_type: Option<String>,
+}
+
+/// 透传给客户端的上游错误响应
+#[derive(Debug)]
+pub struct UpstreamErrorBody {
+    pub content_type: Option<String>,
+    pub body: axum::body::Bytes,
+}
+
+/// 上游对Range请求的响应，上游忽略Range时status为200且content为完整文件
+#[derive(Debug)]
+pub struct UpstreamRangeResponse {
+    pub status: u16,
+    pub content_range: Option<String>,
+    pub content: axum::body::Bytes,
+}
+
+pub type FetchFuture<'a> = Pin<Box<dyn Future<Output = Result<UpstreamResponse, DownloadError>> + Send + 'a>>;
+
+pub type RangeFuture<'a> = Pin<Box<dyn Future<Output = Result<UpstreamRangeResponse, DownloadError>> + Send + 'a>>;
+
+pub type ListFuture<'a> = Pin<Box<dyn Future<Output = Result<Vec<String>, DownloadError>> + Send + 'a>>;
+
+/// 将 (包名, 版本, 文件) 转换为上游地址并下载，
+/// 不同的CDN（unpkg、jsdelivr、私有registry等）实现各自的解析方式
+pub trait UpstreamResolver: Send + Sync {
+    /// 文件在上游的完整地址
+    fn url(&self, package_name: &str, version: &str, file_path: &str) -> String;
+
+    /// 将上游地址解析回 (包名, 版本, 文件)，不是该上游的地址时返回None
+    fn parse_url(&self, url: &str) -> Option<(String, String, String)>;
+
+    /// 返回改用另一个基础地址的解析器，用于单个请求的上游覆盖；不支持时返回None
+    fn with_base_url(&self, _base_url: &str) -> Option<Box<dyn UpstreamResolver>> {
+        None
+    }
+
+    /// 下载文件内容，非2xx响应视为错误
+    fn fetch<'a>(&'a self, package_name: &'a str, version: &'a str, file_path: &'a str) -> FetchFuture<'a>;
+
+    /// 携带Range请求头只下载文件的一部分，不支持时返回None
+    fn fetch_range<'a>(
+        &'a self,
+        _package_name: &'a str,
+        _version: &'a str,
+        _file_path: &'a str,
+        _range: &'a str,
+    ) -> Option<RangeFuture<'a>> {
+        None
+    }
+
+    /// 列出包中所有文件相对包根目录的路径（用于预取时展开目录和通配符），不支持时返回None
+    fn list_files<'a>(&'a self, _package_name: &'a str, _version: &'a str) -> Option<ListFuture<'a>> {
+        None
+    }
+}
+
+/// 默认实现：按unpkg的 `/<包名>@<版本>/<文件>` 格式请求
+#[derive(Clone)]
+pub struct UnpkgResolver {
+    client: reqwest::Client,
+    headers: reqwest::header::HeaderMap,
+    base_url: String,
+    /// 上游的重定向不跟随也不缓存，直接返回给客户端
+    redirect_passthrough: bool,
+    /// 上游错误的状态码和响应体原样返回，关闭时只返回不含细节的消息
+    transparent_errors: bool,
+}
+
+impl UnpkgResolver {
+    pub fn new(
+        client: reqwest::Client,
+        headers: reqwest::header::HeaderMap,
+        base_url: &str,
+        redirect_passthrough: bool,
+        transparent_errors: bool,
+    ) -> Self {
+        Self {
+            client,
+            headers,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            redirect_passthrough,
+            transparent_errors,
+        }
+    }
+
+    /// 返回给客户端的错误消息，只有开启透传时才附带上游细节（可能包含上游地址）
+    fn error_message(&self, message: &str, detail: impl std::fmt::Display) -> String {
+        if self.transparent_errors {
+            format!("{}: {}", message, detail)
+        } else {
+            message.to_string()
+        }
+    }
+}
+
+impl UpstreamResolver for UnpkgResolver {
+    fn url(&self, package_name: &str, version: &str, file_path: &str) -> String {
+        format!("{}/{}@{}/{}", self.base_url, package_name, version, file_path)
+    }
+
+    fn with_base_url(&self, base_url: &str) -> Option<Box<dyn UpstreamResolver>> {
+        let mut resolver = self.clone();
+        resolver.base_url = base_url.trim_end_matches('/').to_string();
+        Some(Box::new(resolver))
+    }
+
+    fn parse_url(&self, url: &str) -> Option<(String, String, String)> {
+        let path = url.strip_prefix(&self.base_url)?.strip_prefix('/')?;
+        let path = path.split(['?', '#']).next()?;
+        // scoped包名以@开头，版本分隔符是其后的第一个@
+        let at = path.get(1..)?.find('@')? + 1;
+        let (version, file_path) = path[at + 1..].split_once('/')?;
+        if file_path.is_empty() {
+            return None;
+        }
+        Some((path[..at].to_string(), version.to_string(), file_path.to_string()))
+    }
+
+    fn fetch_range<'a>(
+        &'a self,
+        package_name: &'a str,
+        version: &'a str,
+        file_path: &'a str,
+        range: &'a str,
+    ) -> Option<RangeFuture<'a>> {
+        Some(Box::pin(async move {
+            let unpkg_url = self.url(package_name, version, file_path);
+
+            let response = self
+                .client
+                .get(&unpkg_url)
+                .headers(self.headers.clone())
+                .header(reqwest::header::RANGE, range)
+                .send()
+                .await
+                .map_err(|e| {
+                    error!("[Black Hole] Range download failed: {}", e);
+                    DownloadError {
+                        status: StatusCode::INTERNAL_SERVER_ERROR,
+                        message: self.error_message("Download failed", &e),
+                        location: None,
+                        upstream_body: None,
+                    }
+                })?;
+
+            let status = response.status();
+            if !status.is_success() {
+                error!("[Black Hole] unpkg returned error for range request {}: {}", unpkg_url, status);
+                return Err(DownloadError {
+                    status: StatusCode::from_u16(status.as_u16()).unwrap_or(StatusCode::BAD_GATEWAY),
+                    message: format!("unpkg returned error: {}", status),
+                    location: None,
+                    upstream_body: None,
+                });
+            }
+            let content_range = response
+                .headers()
+                .get(reqwest::header::CONTENT_RANGE)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string);
+            let content = response.bytes().await.map_err(|e| {
+                error!("[Black Hole] Failed to read range response: {}", e);
+                DownloadError {
+                    status: StatusCode::BAD_GATEWAY,
+                    message: self.error_message("Failed to read response", &e),
+                    location: None,
+                    upstream_body: None,
+                }
+            })?;
+            Ok(UpstreamRangeResponse {
+                status: status.as_u16(),
+                content_range,
+                content,
+            })
+        }))
+    }
+
+    fn fetch<'a>(&'a self, package_name: &'a str, version: &'a str, file_path: &'a str) -> FetchFuture<'a> {
+        Box::pin(async move {
+            let unpkg_url = self.url(package_name, version, file_path);
+
+            let request = self.client.get(&unpkg_url).headers(self.headers.clone());
+            let response = request.send().await.map_err(|e| {
+                error!("[Black Hole] Download failed: {}", e);
+                DownloadError {
+                    status: StatusCode::INTERNAL_SERVER_ERROR,
+                    message: self.error_message("Download failed", &e),
+                    location: None,
+                    upstream_body: None,
+                }
+            })?;
+
+            if response.status().is_redirection() {
+                let status = response.status();
+                let location = response
+                    .headers()
+                    .get(reqwest::header::LOCATION)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|location| response.url().join(location).ok());
+                if self.redirect_passthrough
+                    && let Some(location) = location
+                {
+                    info!("[Black Hole] Passing upstream redirect to client: {}", location);
+                    return Err(DownloadError {
+                        status: StatusCode::from_u16(status.as_u16()).unwrap_or(StatusCode::FOUND),
+                        message: format!("unpkg redirected to {}", location),
+                        location: Some(location.to_string()),
+                        upstream_body: None,
+                    });
+                }
+                error!("[Black Hole] unpkg redirect was not followed for {}: {}", unpkg_url, status);
+                return Err(DownloadError {
+                    status: StatusCode::BAD_GATEWAY,
+                    message: format!("unpkg redirect was not followed: {}", status),
+                    location: None,
+                    upstream_body: None,
+                });
+            }
+
+            if !response.status().is_success() {
+                let status = response.status();
+                error!("[Black Hole] unpkg returned error for {}: {}", unpkg_url, status);
+                let upstream_body = if self.transparent_errors {
+                    let content_type = response
+                        .headers()
+                        .get(reqwest::header::CONTENT_TYPE)
+                        .and_then(|v| v.to_str().ok())
+                        .map(str::to_string);
+                    let body = response.bytes().await.unwrap_or_default();
+                    Some(UpstreamErrorBody { content_type, body })
+                } else {
+                    None
+                };
+                return Err(DownloadError {
+                    status: StatusCode::from_u16(status.as_u16()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+                    message: format!("unpkg returned error: {}", status),
+                    location: None,
+                    upstream_body,
+                });
+            }
+
+            let final_url = response.url().to_string();
+            let content_type = response
+                .headers()
+                .get(reqwest::header::CONTENT_TYPE)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string);
+            let expected_length = response.content_length();
+            let content = response.bytes().await.map_err(|e| {
+                // 响应体读取失败通常是上游连接中断，同样不能写入缓存
+                error!("[Black Hole] Failed to read response: {}", e);
+                DownloadError {
+                    status: StatusCode::BAD_GATEWAY,
+                    message: self.error_message("Failed to read response", &e),
+                    location: None,
+                    upstream_body: None,
+                }
+            })?;
+
+            // 连接中途断开时可能收到不完整的响应体，不能当作成功写入缓存
+            if let Some(expected) = expected_length
+                && content.len() as u64 != expected
+            {
+                error!(
+                    "[Black Hole] Truncated download from {}: received {} of {} bytes",
+                    unpkg_url,
+                    content.len(),
+                    expected
+                );
+                return Err(DownloadError {
+                    status: StatusCode::BAD_GATEWAY,
+                    message: format!("Truncated download: received {} of {} bytes", content.len(), expected),
+                    location: None,
+                    upstream_body: None,
+                });
+            }
+            Ok(UpstreamResponse {
+                content,
+                final_url,
+                content_type,
+            })
+        })
+    }
+
+    fn list_files<'a>(&'a self, package_name: &'a str, version: &'a str) -> Option<ListFuture<'a>> {
+        Some(Box::pin(async move {
+            let meta_url = format!("{}?meta", self.url(package_name, version, ""));
+
+            let response = self
+                .client
+                .get(&meta_url)
+                .headers(self.headers.clone())
+                .send()
+                .await
+                .map_err(|e| {
+                    error!("[Black Hole] File listing request failed: {}", e);
+                    DownloadError {
+                        status: StatusCode::BAD_GATEWAY,
+                        message: self.error_message("File listing request failed", &e),
+                        location: None,
+                        upstream_body: None,
+                    }
+                })?;
+            let status = response.status();
+            if !status.is_success() {
+                error!("[Black Hole] unpkg returned error for {}: {}", meta_url, status);
+                return Err(DownloadError {
+                    status: StatusCode::from_u16(status.as_u16()).unwrap_or(StatusCode::BAD_GATEWAY),
+                    message: format!("unpkg returned error: {}", status),
+                    location: None,
+                    upstream_body: None,
+                });
+            }
+            let meta: serde_json::Value = response.json().await.map_err(|e| {
+                error!("[Black Hole] Failed to parse file listing: {}", e);
+                DownloadError {
+                    status: StatusCode::BAD_GATEWAY,
+                    message: self.error_message("Failed to parse file listing", &e),
+                    location: None,
+                    upstream_body: None,
+                }
+            })?;
+            let mut files = Vec::new();
+            collect_meta_files(&meta, &mut files);
+            Ok(files)
+        }))
+    }
+}
+
+/// 从unpkg的 `?meta` 响应中收集文件路径：旧格式是嵌套的目录树，新格式是平铺的files列表，
+/// 两者的文件都是带有 `"type": "file"` 和以 `/` 开头的path的对象
+fn collect_meta_files(node: &serde_json::Value, files: &mut Vec<String>) {
+    if node.get("type").and_then(|v| v.as_str()) == Some("file")
+        && let Some(path) = node.get("path").and_then(|v| v.as_str())
+    {
+        files.push(path.trim_start_matches('/').to_string());
+    }
+    if let Some(children) = node.get("files").and_then(|v| v.as_array()) {
+        for child in children {
+            collect_meta_files(child, files);
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use serde::{de, Deserialize, Deserializer, Serialize};
+use std::path::Path;
+use tracing::{info, warn};
+
+use crate::{cache_walk, compression, eviction, locks::CacheLocks, sidecar, AppState, ProxyConfig};
+
+#[derive(Debug, Default, Deserialize)]
+pub struct VerifyParams {
+    /// 是否删除校验失败的缓存文件
+    #[serde(default, deserialize_with = "deserialize_flag")]
+    delete: bool,
+}
+
+/// 查询参数中的开关，接受 `1`/`true` 和 `0`/`false`
+fn deserialize_flag<'de, D: Deserializer<'de>>(deserializer: D) -> Result<bool, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    match value.as_str() {
+        "1" | "true" => Ok(true),
+        "0" | "false" => Ok(false),
+        _ => Err(de::Error::invalid_value(de::Unexpected::Str(&value), &"1, true, 0 or false")),
+    }
+}
+
+/// 一次完整性校验的结果
+#[derive(Debug, Default, Serialize)]
+pub struct VerifyReport {
+    pub checked: usize,
+    pub ok: usize,
+    /// 内容与元数据中的大小或摘要不一致的文件
+    pub corrupt: Vec<String>,
+    /// 没有元数据、无法校验的文件
+    pub missing_sidecar: Vec<String>,
+    /// 已删除的损坏文件数
+    pub deleted: usize,
+}
+
+/// 逐个读取缓存文件，与旁路元数据中记录的大小和摘要比较；
+/// 校验和删除在对应路径的写锁内进行，不会把正在写入的文件当作损坏删除
+pub fn run(config: &ProxyConfig, locks: &CacheLocks, delete: bool) -> VerifyReport {
+    let mut report = VerifyReport::default();
+    let root = Path::new(&config.cache_dir);
+    for entry in cache_walk::collect(&config.cache_dir) {
+        report.checked += 1;
+        let _guard = locks.blocking_lock(&entry.path);
+        let Some(metadata) = sidecar::read_sync(&config.cache_dir, &entry.path) else {
+            report.missing_sidecar.push(entry.relative);
+            continue;
+        };
+        let valid = std::fs::read(&entry.path)
+            .and_then(|content| {
+                if metadata.compressed {
+                    compression::gunzip(&content)
+                } else {
+                    Ok(content)
+                }
+            })
+            .is_ok_and(|content| metadata.matches(&content));
+        if valid {
+            report.ok += 1;
+            continue;
+        }
+        warn!("[Black Hole] Cache file failed verification: {}", entry.relative);
+        if delete && eviction::remove_entry(&config.cache_dir, root, &entry).is_some() {
+            report.deleted += 1;
+        }
+        report.corrupt.push(entry.relative);
+    }
+    report
+}
+
+/// 处理 `POST /cache/verify`，带 `?delete=1` 或 `?delete=true` 时删除校验失败的文件
+pub async fn handle_cache_verify(
+    State(state): State<AppState>,
+    Query(params): Query<VerifyParams>,
+) -> Json<VerifyReport> {
+    let config = state.config.proxy.clone();
+    let locks = state.cache_locks.clone();
+    let delete = params.delete;
+    let report = tokio::task::spawn_blocking(move || run(&config, &locks, delete))
+        .await
+        .unwrap_or_default();
+    info!(
+        "[Black Hole] Cache verification checked {} files: {} ok, {} corrupt ({} deleted), {} without metadata",
+        report.checked,
+        report.ok,
+        report.corrupt.len(),
+        report.deleted,
+        report.missing_sidecar.len()
+    );
+    Json(report)
+}